@@ -0,0 +1,162 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::util::TileSize;
+
+const WARMUP_FRAMES: usize = 10;
+const MEASURED_FRAMES: usize = 120;
+
+pub enum SweepStep {
+    Continue,
+    Switch(TileSize),
+    Done,
+}
+
+/// Renders a fixed number of frames with each tile size in turn and records the mean GPU time
+/// of a frame (submit until the queue is idle, so surface acquisition and vsync are excluded).
+pub struct TileSweep {
+    sizes: Vec<TileSize>,
+    current: usize,
+    frames: usize,
+    elapsed: Duration,
+    results: Vec<(TileSize, Duration)>,
+}
+
+impl TileSweep {
+    pub fn new(sizes: Vec<TileSize>) -> Option<Self> {
+        (!sizes.is_empty()).then_some(Self {
+            sizes,
+            current: 0,
+            frames: 0,
+            elapsed: Duration::ZERO,
+            results: vec![],
+        })
+    }
+
+    pub fn tile_size(&self) -> TileSize {
+        self.sizes[self.current]
+    }
+
+    pub fn record(&mut self, frame_time: Duration) -> SweepStep {
+        self.frames += 1;
+        if self.frames <= WARMUP_FRAMES {
+            return SweepStep::Continue;
+        }
+        self.elapsed += frame_time;
+        if self.frames < WARMUP_FRAMES + MEASURED_FRAMES {
+            return SweepStep::Continue;
+        }
+
+        self.results
+            .push((self.tile_size(), self.elapsed / MEASURED_FRAMES as u32));
+        self.frames = 0;
+        self.elapsed = Duration::ZERO;
+        self.current += 1;
+
+        if self.current < self.sizes.len() {
+            SweepStep::Switch(self.tile_size())
+        } else {
+            SweepStep::Done
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = format!("{:>9}  {:>10}\n", "tile", "frame (ms)");
+        for (tile, time) in &self.results {
+            report += &format!(
+                "{:>9}  {:>10.3}\n",
+                tile.to_string(),
+                time.as_secs_f64() * 1000.0
+            );
+        }
+        if let Some((best, _)) = self.results.iter().min_by_key(|(_, time)| *time) {
+            report += &format!("fastest: {}\n", best);
+        }
+        report
+    }
+}
+
+/// Bounded history of frame-to-frame intervals for spotting stutters the FPS average hides.
+pub struct FrameStats {
+    frame_times: VecDeque<Duration>,
+}
+
+impl FrameStats {
+    pub const CAPACITY: usize = 1000;
+
+    pub fn new() -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(Self::CAPACITY),
+        }
+    }
+
+    pub fn record(&mut self, frame_time: Duration) {
+        if self.frame_times.len() == Self::CAPACITY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+    }
+
+    /// Nearest-rank percentile, `p` in `[0, 100]`.
+    fn percentile(sorted: &[Duration], p: f64) -> Duration {
+        let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+
+    /// Markdown table of the recorded frame times in milliseconds.
+    pub fn report(&self) -> String {
+        if self.frame_times.is_empty() {
+            return "no frames recorded\n".to_owned();
+        }
+
+        let mut sorted: Vec<_> = self.frame_times.iter().copied().collect();
+        sorted.sort();
+        let mean = sorted.iter().sum::<Duration>() / sorted.len() as u32;
+        let ms = |d: Duration| format!("{:.3}", d.as_secs_f64() * 1000.0);
+
+        format!(
+            "| frames | min (ms) | mean (ms) | median (ms) | p95 (ms) | p99 (ms) | max (ms) |\n\
+             |-------:|---------:|----------:|------------:|---------:|---------:|---------:|\n\
+             | {} | {} | {} | {} | {} | {} | {} |\n",
+            sorted.len(),
+            ms(sorted[0]),
+            ms(mean),
+            ms(Self::percentile(&sorted, 50.0)),
+            ms(Self::percentile(&sorted, 95.0)),
+            ms(Self::percentile(&sorted, 99.0)),
+            ms(sorted[sorted.len() - 1]),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_stats_keep_the_last_capacity_frames() {
+        let mut stats = FrameStats::new();
+        // Recorded in descending order so the report has to sort them.
+        for ms in (1..=1500).rev() {
+            stats.record(Duration::from_millis(ms));
+        }
+        assert_eq!(stats.frame_times.len(), FrameStats::CAPACITY);
+
+        // 1000 ms down to 1 ms remain: nearest-rank picks the 500th, 950th and 990th.
+        let row = stats.report().lines().nth(2).unwrap().to_owned();
+        assert_eq!(
+            row,
+            "| 1000 | 1.000 | 500.500 | 500.000 | 950.000 | 990.000 | 1000.000 |"
+        );
+    }
+
+    #[test]
+    fn percentile_is_nearest_rank() {
+        let sorted: Vec<_> = (1..=10).map(Duration::from_millis).collect();
+        let at = |p| FrameStats::percentile(&sorted, p).as_millis();
+        assert_eq!(at(0.0), 1);
+        assert_eq!(at(50.0), 5);
+        assert_eq!(at(51.0), 6);
+        assert_eq!(at(95.0), 10);
+        assert_eq!(at(100.0), 10);
+    }
+}
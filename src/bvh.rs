@@ -0,0 +1,277 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    geometry::{aabb::Aabb, Geometry, HitRecord},
+    ray::Ray,
+    util::Vec3,
+};
+
+/// Node of the flattened BVH, laid out for a WGSL storage buffer.
+///
+/// Nodes are stored depth first, so the left child of an interior node directly follows it and
+/// `left_index` is only spelled out for the shader's convenience; `right_or_primitive` is the
+/// index of the right child. Leaves hold a single primitive: `left_index` is `LEAF` and
+/// `right_or_primitive` indexes the primitive list the BVH was built from.
+///
+/// WGSL doesn't promise that infinities survive arithmetic, so the unbounded boxes of planes are
+/// stored as reaching out to `FAR` instead; that is still far beyond anything a ray hits, and
+/// leaves room to multiply by the inverse of a tiny direction component without overflowing.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct BvhNode {
+    pub min: [f32; 3],
+    pub left_index: u32,
+    pub max: [f32; 3],
+    pub right_or_primitive: u32,
+}
+
+impl BvhNode {
+    pub const LEAF: u32 = u32::MAX;
+    pub const FAR: f32 = 1e15;
+}
+
+enum Node {
+    Leaf {
+        bounds: Aabb,
+        primitive: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        left: Box<Node>,
+        right: Box<Node>,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Bounding volume hierarchy over a list of primitives, split at the median along the longest
+/// axis of the primitives' centroids.
+///
+/// Unbounded primitives such as planes are accepted, but their infinite box makes every node
+/// above them infinite too, so those subtrees are never culled; keep planes out of the BVH where
+/// possible.
+pub struct Bvh {
+    primitives: Vec<Box<dyn Geometry>>,
+    root: Option<Node>,
+}
+
+impl Bvh {
+    pub fn new(primitives: Vec<Box<dyn Geometry>>) -> Self {
+        let bounds: Vec<_> = primitives.iter().map(|p| p.bounding_box()).collect();
+        let mut indices: Vec<_> = (0..primitives.len()).collect();
+        let root = (!indices.is_empty()).then(|| build(&bounds, &mut indices));
+        Self { primitives, root }
+    }
+
+    pub fn primitives(&self) -> &[Box<dyn Geometry>] {
+        &self.primitives
+    }
+
+    /// Hands the primitives back in the order they were given, which the flattened leaves index.
+    pub fn into_primitives(self) -> Vec<Box<dyn Geometry>> {
+        self.primitives
+    }
+
+    pub fn flatten(&self) -> Vec<BvhNode> {
+        let mut nodes = Vec::new();
+        if let Some(root) = &self.root {
+            flatten(root, &mut nodes);
+        }
+        nodes
+    }
+
+    /// Closest hit, culling subtrees whose box the ray misses or only reaches beyond the closest
+    /// hit found so far.
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(usize, HitRecord)> {
+        let mut closest = None;
+        let mut t_max = t_max;
+        let mut stack: Vec<&Node> = self.root.iter().collect();
+        while let Some(node) = stack.pop() {
+            if !node.bounds().hit(ray, t_min, t_max) {
+                continue;
+            }
+            match node {
+                Node::Leaf { primitive, .. } => {
+                    if let Some(hit) = self.primitives[*primitive].hit(ray, t_min, t_max) {
+                        t_max = hit.t;
+                        closest = Some((*primitive, hit));
+                    }
+                }
+                Node::Interior { left, right, .. } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+        closest
+    }
+}
+
+fn build(bounds: &[Aabb], indices: &mut [usize]) -> Node {
+    if let [primitive] = indices {
+        return Node::Leaf {
+            bounds: bounds[*primitive],
+            primitive: *primitive,
+        };
+    }
+
+    let centroid = |i: usize| (bounds[i].min() + bounds[i].max()) * 0.5;
+    let centroid_bounds = indices
+        .iter()
+        .map(|&i| Aabb::new(centroid(i), centroid(i)))
+        .reduce(|a, b| Aabb::surrounding_box(&a, &b))
+        .unwrap();
+    let extent = centroid_bounds.max() - centroid_bounds.min();
+    let axis = (0..3)
+        .max_by(|&a, &b| extent[a].total_cmp(&extent[b]))
+        .unwrap();
+    indices.sort_by(|&a, &b| centroid(a)[axis].total_cmp(&centroid(b)[axis]));
+
+    let (left, right) = indices.split_at_mut(indices.len() / 2);
+    let left = build(bounds, left);
+    let right = build(bounds, right);
+    Node::Interior {
+        bounds: Aabb::surrounding_box(left.bounds(), right.bounds()),
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+fn flatten(node: &Node, nodes: &mut Vec<BvhNode>) -> u32 {
+    let index = nodes.len();
+    let bounds = node.bounds();
+    let clamp = |corner: Vec3| {
+        corner
+            .as_array()
+            .map(|c| c.clamp(-BvhNode::FAR, BvhNode::FAR))
+    };
+    nodes.push(BvhNode {
+        min: clamp(bounds.min()),
+        left_index: BvhNode::LEAF,
+        max: clamp(bounds.max()),
+        right_or_primitive: 0,
+    });
+    match node {
+        Node::Leaf { primitive, .. } => nodes[index].right_or_primitive = *primitive as u32,
+        Node::Interior { left, right, .. } => {
+            nodes[index].left_index = flatten(left, nodes);
+            nodes[index].right_or_primitive = flatten(right, nodes);
+        }
+    }
+    index as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::geometry::{plane::Plane, sphere::Sphere, triangle::Triangle};
+
+    fn random_point(rng: &mut StdRng, extent: f32) -> Vec3 {
+        Vec3(
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+        )
+    }
+
+    fn random_scene(rng: &mut StdRng) -> Vec<Box<dyn Geometry>> {
+        let mut primitives: Vec<Box<dyn Geometry>> = Vec::new();
+        for _ in 0..40 {
+            primitives.push(Box::new(Sphere::new(
+                random_point(rng, 10.0),
+                rng.gen_range(0.1..1.5),
+            )));
+        }
+        for _ in 0..20 {
+            let v0 = random_point(rng, 10.0);
+            primitives.push(Box::new(Triangle::new(
+                v0,
+                v0 + random_point(rng, 2.0),
+                v0 + random_point(rng, 2.0),
+            )));
+        }
+        primitives.push(Box::new(Plane::new(Vec3(0.0, -12.0, 0.0), Vec3::j())));
+        primitives
+    }
+
+    fn brute_force(primitives: &[Box<dyn Geometry>], ray: &Ray) -> Option<(usize, HitRecord)> {
+        primitives
+            .iter()
+            .enumerate()
+            .filter_map(|(i, primitive)| Some((i, primitive.hit(ray, 0.001, f32::INFINITY)?)))
+            .min_by(|(_, a), (_, b)| a.t.total_cmp(&b.t))
+    }
+
+    #[test]
+    fn hit_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(513);
+        let bvh = Bvh::new(random_scene(&mut rng));
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let ray = Ray::new(random_point(&mut rng, 15.0), random_point(&mut rng, 1.0));
+            let expected = brute_force(bvh.primitives(), &ray);
+            let actual = bvh.hit(&ray, 0.001, f32::INFINITY);
+            assert_eq!(
+                actual.map(|(i, hit)| (i, hit.t)),
+                expected.map(|(i, hit)| (i, hit.t)),
+                "{:?}",
+                ray
+            );
+            hits += expected.is_some() as usize;
+        }
+        // Make sure the rays aren't all missing.
+        assert!(hits > 500, "only {} hits", hits);
+    }
+
+    #[test]
+    fn empty_bvh_misses() {
+        let bvh = Bvh::new(Vec::new());
+        assert!(bvh.flatten().is_empty());
+        assert!(bvh
+            .hit(&Ray::new(Vec3::origin(), -Vec3::k()), 0.001, f32::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn flatten_is_depth_first_with_one_leaf_per_primitive() {
+        let mut rng = StdRng::seed_from_u64(513);
+        let bvh = Bvh::new(random_scene(&mut rng));
+        let count = bvh.primitives().len();
+        let nodes = bvh.flatten();
+        assert_eq!(nodes.len(), 2 * count - 1);
+
+        let mut leaves: Vec<_> = nodes
+            .iter()
+            .filter(|node| node.left_index == BvhNode::LEAF)
+            .map(|node| node.right_or_primitive as usize)
+            .collect();
+        leaves.sort();
+        assert_eq!(leaves, (0..count).collect::<Vec<_>>());
+
+        for (i, node) in nodes.iter().enumerate() {
+            if node.left_index != BvhNode::LEAF {
+                assert_eq!(node.left_index as usize, i + 1);
+                assert!(node.right_or_primitive as usize > i + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn flatten_keeps_plane_bounds_finite() {
+        let bvh = Bvh::new(vec![
+            Box::new(Plane::new(Vec3::origin(), Vec3::j())),
+            Box::new(Sphere::new(Vec3::origin(), 1.0)),
+        ]);
+        for node in bvh.flatten() {
+            assert!(node.min.iter().chain(&node.max).all(|c| c.is_finite()));
+        }
+    }
+}
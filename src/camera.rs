@@ -1,5 +1,6 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, str::FromStr};
 
+use bytemuck::{Pod, Zeroable};
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
@@ -8,104 +9,178 @@ use wgpu::{
 };
 use winit::dpi::PhysicalSize;
 
-use crate::util::Vec3;
+use crate::util::{Vec3, Vec3Uniform};
 
+/// How pixels are mapped to primary rays.
+///
+/// `Orthographic` fires parallel rays along the view direction, from a grid of origins spanning
+/// `scale` world units vertically around the camera origin. Distance doesn't change the size of
+/// objects and there is no depth of field.
+///
+/// `Equirectangular` covers the full sphere around the camera origin: the horizontal axis spans
+/// longitude -180°..180° with the view direction in the middle of the image and the vertical axis
+/// spans latitude 90°..-90° from the camera's up vector. It ignores the planar viewport otherwise
+/// and needs a 2:1 image.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ProjectionMode {
+    #[default]
+    Perspective,
+    Orthographic {
+        scale: f32,
+    },
+    Equirectangular,
+}
+
+impl ProjectionMode {
+    /// Used when `orthographic` is given without a scale; matches the height of the perspective
+    /// viewport.
+    pub const DEFAULT_ORTHOGRAPHIC_SCALE: f32 = 2.0;
+
+    /// Aspect ratio the image must have for this projection, if it imposes one.
+    pub fn required_aspect(&self) -> Option<f32> {
+        match self {
+            ProjectionMode::Perspective | ProjectionMode::Orthographic { .. } => None,
+            ProjectionMode::Equirectangular => Some(2.0),
+        }
+    }
+
+    fn as_uniform(&self) -> u32 {
+        match self {
+            ProjectionMode::Perspective => 0,
+            ProjectionMode::Equirectangular => 1,
+            ProjectionMode::Orthographic { .. } => 2,
+        }
+    }
+}
+
+impl FromStr for ProjectionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, scale) = match s.split_once(':') {
+            Some((name, scale)) => (name, Some(scale)),
+            None => (s, None),
+        };
+        match (name, scale) {
+            ("perspective", None) => Ok(ProjectionMode::Perspective),
+            ("equirectangular" | "360", None) => Ok(ProjectionMode::Equirectangular),
+            ("orthographic" | "ortho", None) => Ok(ProjectionMode::Orthographic {
+                scale: Self::DEFAULT_ORTHOGRAPHIC_SCALE,
+            }),
+            ("orthographic" | "ortho", Some(scale)) => match scale.parse::<f32>() {
+                Ok(scale) if scale > 0.0 => Ok(ProjectionMode::Orthographic { scale }),
+                _ => Err(format!(
+                    "invalid orthographic scale `{}`, expected a positive number",
+                    scale
+                )),
+            },
+            _ => Err(format!(
+                "unknown projection `{}`, expected perspective, orthographic[:SCALE] or equirectangular",
+                s
+            )),
+        }
+    }
+}
+
+/// `Camera` in `compute.wgsl`. In orthographic mode `pixel_00`, `pixel_du` and `pixel_dv` lay
+/// out ray origins rather than points the rays pass through.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct CameraUniform {
+    origin: Vec3Uniform,
+    pixel_00: Vec3Uniform,
+    pixel_du: Vec3Uniform,
+    pixel_dv: Vec3Uniform,
+    defocus_u: Vec3Uniform,
+    defocus_v: Vec3Uniform,
+    projection: u32,
+    _pad: [u32; 3],
+}
+
+/// With an `aperture` above 0, primary rays start from random points on a lens disk of that
+/// diameter instead of the origin, all aimed through the viewport at `focus_dist`. Objects at that
+/// distance stay sharp and everything nearer or further is blurred.
 pub struct Camera {
     pub origin: Vec3,
     pub viewport: Viewport,
-    origin_buffer: Buffer,
-    viewport_buffers: [Buffer; 2],
     pixel_00_center: Vec3,
-    pixel_buffer: Buffer,
+    projection: ProjectionMode,
+    aperture: f32,
+    focus_dist: f32,
+    buffer: Buffer,
+    /// Set by changes that can't upload right away; `sync_to_gpu` takes care of them.
+    dirty: bool,
 }
 
 impl Camera {
     pub fn new(image_size: PhysicalSize<u32>, device: &Device) -> Self {
         let origin = Vec3::origin();
-        let origin_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&origin.as_array()),
-            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-        });
-
         let viewport = Viewport::new(image_size);
-        let viewport_buffers = [
-            device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&viewport.du.as_array()),
-                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-            }),
-            device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&viewport.dv.as_array()),
-                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-            }),
-        ];
-
-        let upper_corner =
-            origin - Vec3(0.0, 0.0, viewport.focal_len) - (viewport.u * 0.5) - (viewport.v * 0.5);
-        let pixel_00_center = upper_corner + (viewport.du + viewport.dv) * 0.5;
-        let pixel_buffer = device.create_buffer_init(&BufferInitDescriptor {
+        let pixel_00_center = pixel_00_center(origin, &viewport);
+        let projection = ProjectionMode::default();
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(&pixel_00_center.as_array()),
+            contents: bytemuck::bytes_of(&CameraUniform {
+                origin: origin.to_uniform(),
+                pixel_00: pixel_00_center.to_uniform(),
+                pixel_du: viewport.du.to_uniform(),
+                pixel_dv: viewport.dv.to_uniform(),
+                projection: projection.as_uniform(),
+                ..Zeroable::zeroed()
+            }),
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
 
         Self {
             origin,
             viewport,
-            origin_buffer,
-            viewport_buffers,
             pixel_00_center,
-            pixel_buffer,
+            projection,
+            aperture: 0.0,
+            focus_dist: 1.0,
+            buffer,
+            dirty: false,
         }
     }
 
+    #[allow(dead_code)]
+    pub fn with_defocus(mut self, aperture: f32, focus_dist: f32) -> Self {
+        self.aperture = aperture.max(0.0);
+        self.focus_dist = focus_dist;
+        self.viewport.focus(focus_dist);
+        self.pixel_00_center = pixel_00_center(self.origin, &self.viewport);
+        self.dirty = true;
+        self
+    }
+
+    pub fn lens_radius(&self) -> f32 {
+        self.aperture / 2.0
+    }
+
+    /// Uploads changes that were made without a queue, and returns whether there were any.
+    pub fn sync_to_gpu(&mut self, queue: &Queue) -> bool {
+        let dirty = self.dirty;
+        if dirty {
+            self.upload(queue);
+        }
+        dirty
+    }
+
+    pub const LAYOUT_ENTRIES: &'static [BindGroupLayoutEntry] = &[BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }];
+
     pub fn bind_group_layout(device: &Device) -> BindGroupLayout {
         device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: None,
-            entries: &[
-                BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::COMPUTE,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: ShaderStages::COMPUTE,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: ShaderStages::COMPUTE,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: ShaderStages::COMPUTE,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
+            entries: Self::LAYOUT_ENTRIES,
         })
     }
 
@@ -113,61 +188,88 @@ impl Camera {
         device.create_bind_group(&BindGroupDescriptor {
             label: None,
             layout: &Self::bind_group_layout(device),
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::Buffer(
-                        self.origin_buffer.as_entire_buffer_binding(),
-                    ),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Buffer(
-                        self.viewport_buffers[0].as_entire_buffer_binding(),
-                    ),
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: BindingResource::Buffer(
-                        self.viewport_buffers[1].as_entire_buffer_binding(),
-                    ),
-                },
-                BindGroupEntry {
-                    binding: 3,
-                    resource: BindingResource::Buffer(self.pixel_buffer.as_entire_buffer_binding()),
-                },
-            ],
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Buffer(self.buffer.as_entire_buffer_binding()),
+            }],
         })
     }
 
     pub fn resize_viewport(&mut self, queue: &Queue, size: PhysicalSize<u32>) {
         self.viewport.resize(size);
-        queue.write_buffer(
-            &self.viewport_buffers[0],
-            0,
-            bytemuck::cast_slice(&self.viewport.du.as_array()),
-        );
-        queue.write_buffer(
-            &self.viewport_buffers[1],
-            0,
-            bytemuck::cast_slice(&self.viewport.dv.as_array()),
-        );
+        self.update_pixel_buffer(queue);
+    }
 
+    pub fn projection(&self) -> ProjectionMode {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: ProjectionMode, queue: &Queue) {
+        self.projection = projection;
+        self.upload(queue);
+    }
+
+    /// Moves the camera to `look_from` and turns it towards `look_at`, rolled so that `v_up`
+    /// points up on screen. `v_up` mustn't be parallel to the view direction.
+    #[allow(dead_code)]
+    pub fn look_at(&mut self, queue: &Queue, look_from: Vec3, look_at: Vec3, v_up: Vec3) {
+        let w = (look_from - look_at).normalize();
+        let u = v_up.cross(w).normalize();
+        let v = w.cross(u);
+
+        self.origin = look_from;
+        self.viewport.orient([u, v, w]);
         self.update_pixel_buffer(queue);
     }
 
     fn update_pixel_buffer(&mut self, queue: &Queue) {
-        let upper_corner = self.origin
-            - Vec3(0.0, 0.0, self.viewport.focal_len)
-            - (self.viewport.u * 0.5)
-            - (self.viewport.v * 0.5);
-        self.pixel_00_center = upper_corner + (self.viewport.du + self.viewport.dv) * 0.5;
-        queue.write_buffer(
-            &self.pixel_buffer,
-            0,
-            bytemuck::cast_slice(&self.pixel_00_center.as_array()),
-        );
+        self.pixel_00_center = pixel_00_center(self.origin, &self.viewport);
+        self.upload(queue);
     }
+
+    /// The first pixel and the steps to its neighbours: on the viewport in front of the camera,
+    /// or for orthographic projection on the same grid scaled to `scale` and moved back to the
+    /// camera origin.
+    fn pixel_grid(&self) -> (Vec3, Vec3, Vec3) {
+        match self.projection {
+            ProjectionMode::Orthographic { scale } => {
+                let ratio = scale / self.viewport.height;
+                let du = self.viewport.du * ratio;
+                let dv = self.viewport.dv * ratio;
+                let upper_corner =
+                    self.origin - (self.viewport.u + self.viewport.v) * (ratio * 0.5);
+                (upper_corner + (du + dv) * 0.5, du, dv)
+            }
+            ProjectionMode::Perspective | ProjectionMode::Equirectangular => {
+                (self.pixel_00_center, self.viewport.du, self.viewport.dv)
+            }
+        }
+    }
+
+    fn as_uniform(&self) -> CameraUniform {
+        let (pixel_00, pixel_du, pixel_dv) = self.pixel_grid();
+        CameraUniform {
+            origin: self.origin.to_uniform(),
+            pixel_00: pixel_00.to_uniform(),
+            pixel_du: pixel_du.to_uniform(),
+            pixel_dv: pixel_dv.to_uniform(),
+            defocus_u: (self.viewport.basis[0] * self.lens_radius()).to_uniform(),
+            defocus_v: (self.viewport.basis[1] * self.lens_radius()).to_uniform(),
+            projection: self.projection.as_uniform(),
+            _pad: [0; 3],
+        }
+    }
+
+    fn upload(&mut self, queue: &Queue) {
+        self.dirty = false;
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&self.as_uniform()));
+    }
+}
+
+fn pixel_00_center(origin: Vec3, viewport: &Viewport) -> Vec3 {
+    let upper_corner =
+        origin - viewport.basis[2] * viewport.focal_len - (viewport.u * 0.5) - (viewport.v * 0.5);
+    upper_corner + (viewport.du + viewport.dv) * 0.5
 }
 
 impl Debug for Camera {
@@ -176,15 +278,23 @@ impl Debug for Camera {
             .field("origin", &self.origin)
             .field("viewport", &self.viewport)
             .field("first pixel center", &self.pixel_00_center)
+            .field("projection", &self.projection)
+            .field("aperture", &self.aperture)
+            .field("focus distance", &self.focus_dist)
             .finish()
     }
 }
 
+/// The plane the primary rays pass through, `focal_len` in front of the camera. `basis` holds the
+/// camera's unit right, up and backward vectors; `u` and `v` span the viewport across and down
+/// and `du`, `dv` are their per-pixel steps.
 #[derive(Debug)]
 pub struct Viewport {
     width: f32,
     height: f32,
     focal_len: f32,
+    image_size: PhysicalSize<u32>,
+    basis: [Vec3; 3],
     u: Vec3,
     v: Vec3,
     du: Vec3,
@@ -193,36 +303,208 @@ pub struct Viewport {
 
 impl Viewport {
     pub fn new(image_size: PhysicalSize<u32>) -> Self {
-        let height = 2.0;
-        let width = height * (image_size.width as f32 / image_size.height as f32);
-
-        let u = Vec3(width, 0.0, 0.0);
-        let v = Vec3(0.0, -height, 0.0);
-
-        let du = u / image_size.width as f32;
-        let dv = v / image_size.height as f32;
-
-        Self {
-            height,
-            width,
+        let mut viewport = Self {
+            height: 2.0,
+            width: 0.0,
             focal_len: 1.0,
-            u,
-            v,
-            du,
-            dv,
-        }
+            image_size,
+            basis: [Vec3::i(), Vec3::j(), Vec3::k()],
+            u: Vec3::origin(),
+            v: Vec3::origin(),
+            du: Vec3::origin(),
+            dv: Vec3::origin(),
+        };
+        viewport.update();
+        viewport
     }
 
+    #[allow(dead_code)]
     pub fn with_focal_len(mut self, focal_len: f32) -> Self {
         self.focal_len = focal_len;
         self
     }
 
     pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.image_size = size;
+        self.update();
+    }
+
+    /// Moves the viewport to `distance` from the camera, scaled to keep the field of view.
+    pub fn focus(&mut self, distance: f32) {
+        self.height *= distance / self.focal_len;
+        self.focal_len = distance;
+        self.update();
+    }
+
+    /// Sets the camera's right, up and backward unit vectors.
+    pub fn orient(&mut self, basis: [Vec3; 3]) {
+        self.basis = basis;
+        self.update();
+    }
+
+    fn update(&mut self) {
+        let size = self.image_size;
         self.width = self.height * (size.width as f32 / size.height as f32);
-        self.u = Vec3(self.width, 0.0, 0.0);
+        self.u = self.basis[0] * self.width;
+        self.v = self.basis[1] * -self.height;
 
         self.du = self.u / size.width as f32;
         self.dv = self.v / size.height as f32;
     }
 }
+
+/// Read-only accessors. The renderer never reads the viewport back, but tests and tools can.
+#[allow(dead_code)]
+impl Viewport {
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    pub fn focal_len(&self) -> f32 {
+        self.focal_len
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.u
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.v
+    }
+
+    pub fn du(&self) -> Vec3 {
+        self.du
+    }
+
+    pub fn dv(&self) -> Vec3 {
+        self.dv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{offset_of, size_of};
+
+    use super::*;
+    use crate::util::test_device;
+
+    #[test]
+    fn uniform_matches_wgsl_layout() {
+        assert_eq!(size_of::<CameraUniform>() % 16, 0);
+        assert_eq!(size_of::<CameraUniform>(), 112);
+        assert_eq!(offset_of!(CameraUniform, defocus_v), 80);
+        assert_eq!(offset_of!(CameraUniform, projection), 96);
+    }
+
+    fn camera() -> (Camera, Queue) {
+        let (device, queue) = test_device();
+        (Camera::new(PhysicalSize::new(160, 90), &device), queue)
+    }
+
+    #[test]
+    fn default_look_at_keeps_the_initial_frame() {
+        let (mut camera, queue) = camera();
+        let initial = camera.as_uniform();
+        camera.look_at(&queue, Vec3::origin(), -Vec3::k(), Vec3::j());
+        assert_eq!(camera.viewport.basis, [Vec3::i(), Vec3::j(), Vec3::k()]);
+        assert_eq!(
+            bytemuck::bytes_of(&camera.as_uniform()),
+            bytemuck::bytes_of(&initial)
+        );
+    }
+
+    #[test]
+    fn looking_at_the_origin_from_z_keeps_the_default_frame() {
+        let (mut camera, queue) = camera();
+        let initial = camera.as_uniform();
+        camera.look_at(&queue, Vec3::k(), Vec3::origin(), Vec3::j());
+        assert_eq!(camera.viewport.basis, [Vec3::i(), Vec3::j(), Vec3::k()]);
+
+        // The same frame, moved back by one.
+        let uniform = camera.as_uniform();
+        assert_eq!(uniform.origin.xyz, [0.0, 0.0, 1.0]);
+        assert_eq!(uniform.pixel_du.xyz, initial.pixel_du.xyz);
+        assert_eq!(uniform.pixel_dv.xyz, initial.pixel_dv.xyz);
+        let [x, y, z] = initial.pixel_00.xyz;
+        assert_eq!(uniform.pixel_00.xyz, [x, y, z + 1.0]);
+    }
+
+    #[test]
+    fn look_at_turns_towards_the_target() {
+        let (mut camera, queue) = camera();
+        let look_from = Vec3(3.0, 0.0, 0.0);
+        camera.look_at(&queue, look_from, Vec3::origin(), Vec3::j());
+
+        let [right, up, back] = camera.viewport.basis;
+        assert!((back - Vec3::i()).length() < 1e-6);
+        assert!((up - Vec3::j()).length() < 1e-6);
+        assert!((right - -Vec3::k()).length() < 1e-6);
+
+        // The image centre lies on the line of sight, one focal length ahead.
+        let uniform = camera.as_uniform();
+        let [x, y, z] = uniform.pixel_00.xyz;
+        let pixel_00 = Vec3(x, y, z);
+        let center = pixel_00
+            + (camera.viewport.u - camera.viewport.du) * 0.5
+            + (camera.viewport.v - camera.viewport.dv) * 0.5;
+        assert!((center - Vec3(2.0, 0.0, 0.0)).length() < 1e-5);
+        assert_eq!(uniform.origin.xyz, look_from.as_array());
+    }
+
+    #[test]
+    fn zero_aperture_starts_every_ray_at_the_origin() {
+        let (camera, _) = camera();
+        let camera = camera.with_defocus(0.0, 3.0);
+        let uniform = camera.as_uniform();
+        // The shader offsets ray origins by defocus_u and defocus_v scaled by a disk sample.
+        assert_eq!(uniform.defocus_u.xyz, [0.0; 3]);
+        assert_eq!(uniform.defocus_v.xyz, [0.0; 3]);
+    }
+
+    #[test]
+    fn defocus_spans_the_lens_and_moves_the_viewport() {
+        let (camera, _) = camera();
+        let height = camera.viewport.height();
+        let camera = camera.with_defocus(0.5, 4.0);
+        assert_eq!(camera.lens_radius(), 0.25);
+        assert_eq!(camera.as_uniform().defocus_u.xyz, [0.25, 0.0, 0.0]);
+        assert_eq!(camera.as_uniform().defocus_v.xyz, [0.0, 0.25, 0.0]);
+        assert_eq!(camera.viewport.focal_len(), 4.0);
+        assert_eq!(camera.viewport.height(), height * 4.0);
+    }
+
+    #[test]
+    fn negative_aperture_is_a_pinhole() {
+        let (camera, _) = camera();
+        assert_eq!(camera.with_defocus(-1.0, 2.0).lens_radius(), 0.0);
+    }
+
+    #[test]
+    fn projection_flag_reaches_the_uniform() {
+        let (mut camera, queue) = camera();
+        assert_eq!(camera.as_uniform().projection, 0);
+        camera.set_projection(ProjectionMode::Equirectangular, &queue);
+        assert_eq!(camera.as_uniform().projection, 1);
+        camera.set_projection(ProjectionMode::Orthographic { scale: 4.0 }, &queue);
+        assert_eq!(camera.as_uniform().projection, 2);
+        assert_eq!(
+            camera.projection(),
+            ProjectionMode::Orthographic { scale: 4.0 }
+        );
+    }
+
+    #[test]
+    fn orthographic_grid_is_centred_on_the_origin() {
+        let (mut camera, queue) = camera();
+        camera.set_projection(ProjectionMode::Orthographic { scale: 4.0 }, &queue);
+        let (pixel_00, du, dv) = camera.pixel_grid();
+        let [width, height] = [160.0, 90.0];
+        let center = pixel_00 + du * ((width - 1.0) / 2.0) + dv * ((height - 1.0) / 2.0);
+        assert!((center - camera.origin).length() < 1e-5);
+        assert!(((dv * height).length() - 4.0).abs() < 1e-5);
+    }
+}
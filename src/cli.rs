@@ -0,0 +1,197 @@
+use wgpu::PowerPreference;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    camera::ProjectionMode, config::Config, schedule::TileOrder, settings::ToneMap, util::TileSize,
+};
+
+#[derive(Default, Debug)]
+pub struct Args {
+    pub config: Option<String>,
+    pub window_size: Option<PhysicalSize<u32>>,
+    pub vsync: Option<bool>,
+    pub power_preference: PowerPreference,
+    pub tile_sizes: Vec<TileSize>,
+    pub tile_order: Option<TileOrder>,
+    pub aspect: Option<f32>,
+    pub internal_res: Option<PhysicalSize<u32>>,
+    pub title: Option<String>,
+    pub fps_cap: u32,
+    pub dump_layout: bool,
+    pub info: bool,
+    pub software: bool,
+    pub direct_present: bool,
+    pub lut: Option<String>,
+    pub environment: Option<String>,
+    pub projection: Option<ProjectionMode>,
+    pub near: Option<f32>,
+    pub samples: Option<u32>,
+    pub max_depth: Option<u32>,
+    pub tone_map: Option<ToneMap>,
+}
+
+impl Args {
+    /// Parses the command line on top of the config file it names, or `config.toml`.
+    pub fn parse() -> Result<Self, String> {
+        let args: Vec<_> = std::env::args().skip(1).collect();
+        let config = match Self::parse_from(args.clone())?.config {
+            Some(path) => Config::load(&path)?,
+            None => Config::load_default()?,
+        };
+        let mut parsed = Self::parse_from(config.to_args().into_iter().chain(args))?;
+        parsed.software |= std::env::var_os("RAY_TRACING_SOFTWARE").is_some();
+        Ok(parsed)
+    }
+
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => parsed.config = Some(value(&mut args, &arg)?),
+                "--size" => parsed.window_size = Some(parse_resolution(&value(&mut args, &arg)?)?),
+                "--vsync" => {
+                    parsed.vsync = Some(match value(&mut args, &arg)?.as_str() {
+                        "on" => true,
+                        "off" => false,
+                        other => {
+                            return Err(format!("invalid vsync `{}`, expected on or off", other))
+                        }
+                    })
+                }
+                "--adapter" => {
+                    let adapter = value(&mut args, &arg)?;
+                    (parsed.power_preference, parsed.software) = match adapter.as_str() {
+                        "high-performance" => (PowerPreference::HighPerformance, false),
+                        "low-power" => (PowerPreference::LowPower, false),
+                        "software" => (PowerPreference::None, true),
+                        _ => {
+                            return Err(format!(
+                                "unknown adapter `{}`, expected high-performance, low-power or software",
+                                adapter
+                            ))
+                        }
+                    };
+                }
+                "--tile" => {
+                    parsed.tile_sizes = value(&mut args, &arg)?
+                        .split(',')
+                        .map(str::parse)
+                        .collect::<Result<_, _>>()?;
+                }
+                "--tile-order" => parsed.tile_order = Some(value(&mut args, &arg)?.parse()?),
+                "--aspect" => parsed.aspect = Some(parse_aspect(&value(&mut args, &arg)?)?),
+                "--internal-res" => {
+                    parsed.internal_res = Some(parse_resolution(&value(&mut args, &arg)?)?)
+                }
+                "--fps-cap" => {
+                    let cap = value(&mut args, &arg)?;
+                    parsed.fps_cap = cap
+                        .parse()
+                        .map_err(|_| format!("invalid frame rate cap `{}`", cap))?;
+                }
+                "--title" => parsed.title = Some(value(&mut args, &arg)?),
+                "--dump-layout" => parsed.dump_layout = true,
+                "--info" => parsed.info = true,
+                "--software" => parsed.software = true,
+                "--direct-present" => parsed.direct_present = true,
+                "--lut" => parsed.lut = Some(value(&mut args, &arg)?),
+                "--environment" => parsed.environment = Some(value(&mut args, &arg)?),
+                "--near" => {
+                    let near = value(&mut args, &arg)?;
+                    parsed.near = Some(
+                        near.parse()
+                            .ok()
+                            .filter(|near: &f32| near.is_finite() && *near >= 0.0)
+                            .ok_or_else(|| format!("invalid near distance `{}`", near))?,
+                    );
+                }
+                "--samples" => {
+                    let samples = value(&mut args, &arg)?;
+                    parsed.samples = Some(
+                        samples
+                            .parse()
+                            .ok()
+                            .filter(|samples: &u32| *samples > 0)
+                            .ok_or_else(|| format!("invalid sample count `{}`", samples))?,
+                    );
+                }
+                "--max-depth" => {
+                    let depth = value(&mut args, &arg)?;
+                    parsed.max_depth = Some(
+                        depth
+                            .parse()
+                            .map_err(|_| format!("invalid bounce depth `{}`", depth))?,
+                    );
+                }
+                "--tone-map" => parsed.tone_map = Some(value(&mut args, &arg)?.parse()?),
+                "--projection" => parsed.projection = Some(value(&mut args, &arg)?.parse()?),
+                _ => return Err(format!("unknown argument `{}`", arg)),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn usage() -> &'static str {
+        "\
+usage: ray-tracing-webgpu [options]
+
+    --config PATH         read defaults for these options from a TOML file (default config.toml),
+                          with keys named after the options, e.g. fps-cap = 60
+    --size WxH            initial window size
+    --vsync on|off        wait for vertical blank when presenting (default: the surface's choice)
+    --adapter NAME        high-performance, low-power or software
+    --tile WxH[,WxH...]   compute workgroup tile size; several sizes run a frame-time sweep
+    --tile-order ORDER    linear (default) or morton order of tiles in the dispatch (toggle with O)
+    --aspect W:H          start with the image locked to this aspect ratio (toggle with L)
+    --internal-res WxH    render at this fixed resolution, scaled and letterboxed to the window
+    --fps-cap N           redraw at most N times per second, regardless of vsync (0: uncapped).
+                          Anything that advances per frame still does so, just less often
+    --title TEXT          window title
+    --projection NAME     perspective (default), orthographic[:SCALE] or equirectangular 360°
+                          (toggle 360° with P)
+    --near DISTANCE       clip geometry closer than this to the camera (default 0.001)
+    --samples N           rays traced per pixel and frame, averaged (default 16)
+    --max-depth N         bounces a path may take before it is cut off (default 8)
+    --tone-map NAME       none (default), reinhard or aces curve for radiance above 1 (cycle with M)
+    --lut PATH            colour grading LUT in .cube format (toggle with G)
+    --environment PATH    equirectangular .hdr or .exr image lighting the scene in place of the sky
+    --dump-layout         print the bind group layouts of both pipelines and exit
+    --software            render on a CPU adapter (lavapipe, llvmpipe, WARP) instead of the GPU;
+                          much slower, for machines without one. Also RAY_TRACING_SOFTWARE=1
+    --direct-present      copy the image straight to the window, skipping the render pass, when
+                          the surface supports Rgba8Unorm copies and the image fills the window
+                          with no grading or overlay and gamma encoding (E) is off
+    --info                print the GPU adapter, its limits and supported features and exit"
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("`{}` expects a value", flag))
+}
+
+fn parse_aspect(value: &str) -> Result<f32, String> {
+    let aspect = match value.split_once(':') {
+        Some((width, height)) => width
+            .parse::<f32>()
+            .ok()
+            .zip(height.parse::<f32>().ok())
+            .map(|(width, height)| width / height),
+        None => value.parse().ok(),
+    };
+    aspect
+        .filter(|aspect| aspect.is_finite() && *aspect > 0.0)
+        .ok_or_else(|| format!("invalid aspect ratio `{}`, expected W:H", value))
+}
+
+fn parse_resolution(value: &str) -> Result<PhysicalSize<u32>, String> {
+    value
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .map(|(width, height)| PhysicalSize::new(width, height))
+        .ok_or_else(|| format!("invalid resolution `{}`, expected WxH", value))
+}
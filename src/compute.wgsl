@@ -1,55 +1,517 @@
 @group(0) @binding(0)
 var output_tex: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(1)
+var object_id_tex: texture_storage_2d<r32uint, write>;
+@group(0) @binding(2)
+var blue_noise_tex: texture_2d<f32>;
+// Tile coordinates packed as x | y << 16, indexed by the flattened workgroup id.
+@group(0) @binding(3)
+var<storage, read> tile_order: array<u32>;
+// Running average of the frames so far: the previous frame's is read and this frame's written.
+@group(0) @binding(4)
+var accumulation_in: texture_2d<f32>;
+@group(0) @binding(5)
+var accumulation_out: texture_storage_2d<rgba32float, write>;
+
+const PROJECTION_PERSPECTIVE: u32 = 0u;
+const PROJECTION_EQUIRECTANGULAR: u32 = 1u;
+const PROJECTION_ORTHOGRAPHIC: u32 = 2u;
+
+// Mirrors `CameraUniform`. pixel_00 is the centre of the top-left pixel on the viewport and
+// pixel_du, pixel_dv step one pixel right and down. defocus_u, defocus_v span the lens disk and
+// are zero for a pinhole camera. Orthographic cameras start their rays on the pixel grid instead.
+// `projection` follows the last vector's padding, as `Vec3Uniform` keeps that to itself.
+struct Camera {
+    origin: vec3f,
+    pixel_00: vec3f,
+    pixel_du: vec3f,
+    pixel_dv: vec3f,
+    defocus_u: vec3f,
+    defocus_v: vec3f,
+    @align(16) projection: u32,
+}
 
 @group(1) @binding(0)
-var<uniform> camera_origin: vec3f;
+var<uniform> camera: Camera;
 
-@group(1) @binding(1)
-var<uniform> viewport_du: vec3f;
-@group(1) @binding(2)
-var<uniform> viewport_dv: vec3f;
+struct RenderSettings {
+    frame_index: u32,
+    dither: u32,
+    near: f32,
+    samples_per_pixel: u32,
+    frame_count: u32,
+    max_depth: u32,
+    sky_top: vec3f,
+    sky_bottom: vec3f,
+}
 
-@group(1) @binding(3)
-var<uniform> pixel_00_center: vec3f;
+@group(2) @binding(0)
+var<uniform> settings: RenderSettings;
+
+// Spheres use a as the center plus radius, planes a as a point and b as the normal, triangles
+// a, b, c as vertices. Mirrors `GpuPrimitive`.
+const PRIMITIVE_SPHERE: u32 = 0u;
+const PRIMITIVE_PLANE: u32 = 1u;
+const PRIMITIVE_TRIANGLE: u32 = 2u;
+
+struct Primitive {
+    a: vec3f,
+    kind: u32,
+    b: vec3f,
+    radius: f32,
+    c: vec3f,
+    material: u32,
+}
 
-fn ray_at(ray_direction: vec3f, t: f32) -> vec3f {
-    return camera_origin + t * ray_direction;
+@group(3) @binding(0)
+var<storage, read> primitives: array<Primitive>;
+struct SceneInfo {
+    primitive_count: u32,
+    has_environment: u32,
 }
 
-fn hit_sphere(center: vec3f, radius: f32, ray_direction: vec3f) -> f32 {
-    let o = center - camera_origin;
-    let a = dot(ray_direction, ray_direction);
-    let h = dot(ray_direction, o);
+@group(3) @binding(1)
+var<uniform> scene: SceneInfo;
+
+// Mirrors `GpuMaterial`.
+const MATERIAL_LAMBERTIAN: u32 = 0u;
+const MATERIAL_METAL: u32 = 1u;
+const MATERIAL_DIELECTRIC: u32 = 2u;
+const MATERIAL_DIFFUSE_LIGHT: u32 = 3u;
+
+struct Material {
+    albedo: vec3f,
+    kind: u32,
+    emit: vec3f,
+    fuzz: f32,
+    ior: f32,
+}
+
+@group(3) @binding(2)
+var<storage, read> materials: array<Material>;
+
+// Equirectangular, sampled by rays that miss everything when `scene.has_environment` is set.
+@group(3) @binding(3)
+var environment_tex: texture_2d<f32>;
+@group(3) @binding(4)
+var environment_sampler: sampler;
+
+// Mirrors `BvhNode`: depth first from the root at index 0. Interior nodes have both children,
+// leaves have left_index == BVH_LEAF and the primitive in right_or_primitive.
+const BVH_LEAF: u32 = 0xffffffffu;
+
+struct BvhNode {
+    min: vec3f,
+    left_index: u32,
+    max: vec3f,
+    right_or_primitive: u32,
+}
+
+@group(3) @binding(5)
+var<storage, read> bvh: array<BvhNode>;
+
+const PI: f32 = 3.14159265358979;
+
+// `position` is in pixels from the top-left corner of the image. Longitude runs -pi..pi left to
+// right with the view direction in the centre, latitude pi/2..-pi/2 top to bottom. The camera's
+// axes are recovered from the pixel steps.
+fn equirectangular_direction(position: vec2f, size: vec2u) -> vec3f {
+    let uv = position / vec2f(size);
+    let longitude = (uv.x - 0.5) * 2.0 * PI;
+    let latitude = (0.5 - uv.y) * PI;
+    let right = normalize(camera.pixel_du);
+    let up = -normalize(camera.pixel_dv);
+    let back = cross(right, up);
+    return sin(longitude) * cos(latitude) * right
+        + sin(latitude) * up
+        - cos(longitude) * cos(latitude) * back;
+}
+
+struct Ray {
+    origin: vec3f,
+    direction: vec3f,
+}
+
+fn ray_at(ray: Ray, t: f32) -> vec3f {
+    return ray.origin + t * ray.direction;
+}
+
+// PCG hash (Jarzynski & Olano, "Hash Functions for GPU Rendering"). Seeded per pixel and frame
+// in `main`; every draw advances the state.
+var<private> rng_state: u32;
+
+fn pcg_hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Uniform in [0, 1).
+fn random_f32() -> f32 {
+    rng_state = pcg_hash(rng_state);
+    return f32(rng_state >> 8u) / 16777216.0;
+}
+
+// Uniform on the unit sphere.
+fn random_unit_vector() -> vec3f {
+    let z = random_f32() * 2.0 - 1.0;
+    let phi = random_f32() * 2.0 * PI;
+    let r = sqrt(1.0 - z * z);
+    return vec3f(r * cos(phi), r * sin(phi), z);
+}
+
+// Uniform inside the unit sphere.
+fn random_in_unit_sphere() -> vec3f {
+    return random_unit_vector() * pow(random_f32(), 1.0 / 3.0);
+}
+
+// Uniform on the unit disk.
+fn random_in_unit_disk() -> vec2f {
+    let r = sqrt(random_f32());
+    let theta = random_f32() * 2.0 * PI;
+    return r * vec2f(cos(theta), sin(theta));
+}
+
+// Nearest hit beyond t_min, falling back to the far side when the near one is clipped.
+fn hit_sphere(center: vec3f, radius: f32, ray: Ray, t_min: f32) -> f32 {
+    let o = center - ray.origin;
+    let a = dot(ray.direction, ray.direction);
+    let h = dot(ray.direction, o);
     let c = dot(o, o) - radius * radius;
     let d = h * h - a * c;
 
     if d < 0.0 {
         return -1.0;
-    } else {
-        return (h - sqrt(d)) / a;
     }
+    let near_root = (h - sqrt(d)) / a;
+    if near_root > t_min {
+        return near_root;
+    }
+    let far_root = (h + sqrt(d)) / a;
+    if far_root > t_min {
+        return far_root;
+    }
+    return -1.0;
+}
+
+fn hit_plane(point: vec3f, normal: vec3f, ray: Ray, t_min: f32) -> f32 {
+    let denominator = dot(normal, ray.direction);
+    if abs(denominator) < 1e-8 {
+        return -1.0;
+    }
+    let t = dot(normal, point - ray.origin) / denominator;
+    if t > t_min {
+        return t;
+    }
+    return -1.0;
+}
+
+// Möller–Trumbore, hitting both faces.
+fn hit_triangle(v0: vec3f, v1: vec3f, v2: vec3f, ray: Ray, t_min: f32) -> f32 {
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+    let p = cross(ray.direction, edge2);
+    let determinant = dot(edge1, p);
+    if abs(determinant) < 1e-8 {
+        return -1.0;
+    }
+    let inv_determinant = 1.0 / determinant;
+    let s = ray.origin - v0;
+    let u = dot(s, p) * inv_determinant;
+    if u < 0.0 || u > 1.0 {
+        return -1.0;
+    }
+    let q = cross(s, edge1);
+    let v = dot(ray.direction, q) * inv_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return -1.0;
+    }
+    let t = dot(edge2, q) * inv_determinant;
+    if t > t_min {
+        return t;
+    }
+    return -1.0;
 }
 
-fn get_color(ray_direction: vec3f) -> vec4f {
-    let t = hit_sphere(vec3f(0.0, 0.0, -1.0), 0.5, ray_direction);
-    if t > 0.0 {
-        let n = normalize(ray_at(ray_direction, t) - vec3f(0.0, 0.0, -1.0));
-        return vec4f((n + 1.0) * 0.5, 1.0);
+fn hit_primitive(primitive: Primitive, ray: Ray, t_min: f32) -> f32 {
+    switch primitive.kind {
+        case PRIMITIVE_PLANE: {
+            return hit_plane(primitive.a, primitive.b, ray, t_min);
+        }
+        case PRIMITIVE_TRIANGLE: {
+            return hit_triangle(primitive.a, primitive.b, primitive.c, ray, t_min);
+        }
+        default: {
+            return hit_sphere(primitive.a, primitive.radius, ray, t_min);
+        }
     }
+}
+
+fn outward_normal(primitive: Primitive, point: vec3f) -> vec3f {
+    switch primitive.kind {
+        case PRIMITIVE_PLANE: {
+            return primitive.b;
+        }
+        case PRIMITIVE_TRIANGLE: {
+            return normalize(cross(primitive.b - primitive.a, primitive.c - primitive.a));
+        }
+        default: {
+            return (point - primitive.a) / primitive.radius;
+        }
+    }
+}
+
+const NO_OBJECT: u32 = 0xffffffffu;
 
-    let a = (ray_direction.y + 1.0) * 0.5;
-    return vec4f((1.0 - a) * vec3f(0.8, 0.9, 1.0) + a * vec3f(0.1, 0.3, 1.0), 1.0);
+struct Hit {
+    t: f32,
+    object_id: u32,
 }
 
-@compute @workgroup_size(1)
+// Slab test: whether the ray passes through the box somewhere between t_min and t_max. Mirrors
+// `Aabb::hit`.
+fn hit_aabb(node: BvhNode, ray: Ray, inverse_direction: vec3f, t_min: f32, t_max: f32) -> bool {
+    let t0 = (node.min - ray.origin) * inverse_direction;
+    let t1 = (node.max - ray.origin) * inverse_direction;
+    let near = min(t0, t1);
+    let far = max(t0, t1);
+    let enter = max(t_min, max(near.x, max(near.y, near.z)));
+    let exit = min(t_max, min(far.x, min(far.y, far.z)));
+    return enter < exit;
+}
+
+// Enough for 2^32 primitives split at the median, which is what `Bvh` builds.
+const BVH_STACK_SIZE: u32 = 32u;
+
+// Walks the BVH like `Bvh::hit`, skipping boxes the ray misses or only reaches beyond the closest
+// hit so far.
+fn closest_hit(ray: Ray, t_min: f32) -> Hit {
+    var closest = Hit(-1.0, NO_OBJECT);
+    if scene.primitive_count == 0u {
+        return closest;
+    }
+
+    // Keeps the inverse finite; a tiny component still puts the slab's planes far away.
+    let tiny = abs(ray.direction) < vec3f(1e-20);
+    let inverse_direction = 1.0 / select(ray.direction, vec3f(1e-20), tiny);
+    var t_max = 3.40282347e38;
+    var stack: array<u32, BVH_STACK_SIZE>;
+    stack[0] = 0u;
+    var size = 1u;
+    while size > 0u {
+        size--;
+        let node = bvh[stack[size]];
+        if !hit_aabb(node, ray, inverse_direction, t_min, t_max) {
+            continue;
+        }
+        if node.left_index == BVH_LEAF {
+            let i = node.right_or_primitive;
+            let t = hit_primitive(primitives[i], ray, t_min);
+            if t > 0.0 && t < t_max {
+                t_max = t;
+                closest = Hit(t, i);
+            }
+        } else if size + 2u <= BVH_STACK_SIZE {
+            stack[size] = node.right_or_primitive;
+            stack[size + 1u] = node.left_index;
+            size += 2u;
+        }
+    }
+    return closest;
+}
+
+struct Scatter {
+    absorbed: bool,
+    attenuation: vec3f,
+    direction: vec3f,
+}
+
+// Schlick's approximation. Mirrors `material::reflectance`.
+fn reflectance(cosine: f32, refraction_ratio: f32) -> f32 {
+    let r0 = pow((1.0 - refraction_ratio) / (1.0 + refraction_ratio), 2.0);
+    return r0 + (1.0 - r0) * pow(1.0 - cosine, 5.0);
+}
+
+// `normal` faces against the incoming ray. Mirrors `Material::scatter`.
+fn scatter(material: Material, ray_direction: vec3f, normal: vec3f, front_face: bool) -> Scatter {
+    switch material.kind {
+        case MATERIAL_DIFFUSE_LIGHT: {
+            return Scatter(true, vec3f(0.0), vec3f(0.0));
+        }
+        case MATERIAL_DIELECTRIC: {
+            var ratio = material.ior;
+            if front_face {
+                ratio = 1.0 / material.ior;
+            }
+            let direction = normalize(ray_direction);
+            let cos_theta = min(dot(-direction, normal), 1.0);
+            let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+            let total_internal_reflection = ratio * sin_theta > 1.0;
+            if total_internal_reflection || reflectance(cos_theta, ratio) > random_f32() {
+                return Scatter(false, vec3f(1.0), reflect(direction, normal));
+            }
+            return Scatter(false, vec3f(1.0), refract(direction, normal, ratio));
+        }
+        case MATERIAL_METAL: {
+            let reflected = reflect(normalize(ray_direction), normal)
+                + material.fuzz * random_in_unit_sphere();
+            return Scatter(dot(reflected, normal) <= 0.0, material.albedo, reflected);
+        }
+        default: {
+            var direction = normal + random_unit_vector();
+            if all(abs(direction) < vec3f(1e-8)) {
+                direction = normal;
+            }
+            return Scatter(false, material.albedo, direction);
+        }
+    }
+}
+
+// Inverse of `equirectangular_direction` for a camera looking down -Z, so -Z lands in the centre
+// of the map. Mirrors `scene::environment_uv`.
+fn environment_uv(direction: vec3f) -> vec2f {
+    let d = normalize(direction);
+    let longitude = atan2(d.x, -d.z);
+    let latitude = asin(clamp(d.y, -1.0, 1.0));
+    return vec2f(0.5 + longitude / (2.0 * PI), 0.5 - latitude / PI);
+}
+
+// Without an environment map, the gradient of `RenderSettings::sky`.
+fn sky(ray_direction: vec3f) -> vec3f {
+    if scene.has_environment != 0u {
+        let uv = environment_uv(ray_direction);
+        return textureSampleLevel(environment_tex, environment_sampler, uv, 0.0).rgb;
+    }
+    let a = (normalize(ray_direction).y + 1.0) * 0.5;
+    return mix(settings.sky_bottom, settings.sky_top, a);
+}
+
+// Keeps bounced rays from hitting the surface they start on again due to rounding.
+const BOUNCE_T_MIN: f32 = 0.001;
+
+struct Sample {
+    color: vec4f,
+    object_id: u32,
+}
+
+// Follows the ray through up to `settings.max_depth` bounces, collecting the light emitted by
+// every surface it hits and by the sky it escapes to, each scaled by the attenuation so far. Paths
+// that run out of bounces contribute nothing more, so at a depth of 0 only the sky and lights
+// seen directly remain. Mirrored by `Scene::trace`.
+fn trace(primary: Ray) -> Sample {
+    var ray = primary;
+    var t_min = settings.near / length(ray.direction);
+    var attenuation = vec3f(1.0);
+    var color = vec3f(0.0);
+    var object_id = NO_OBJECT;
+    for (var depth = 0u; depth <= settings.max_depth; depth++) {
+        let hit = closest_hit(ray, t_min);
+        if depth == 0u {
+            object_id = hit.object_id;
+        }
+        if hit.object_id == NO_OBJECT {
+            color += attenuation * sky(ray.direction);
+            break;
+        }
+
+        let primitive = primitives[hit.object_id];
+        let point = ray_at(ray, hit.t);
+        var normal = outward_normal(primitive, point);
+        let front_face = dot(ray.direction, normal) < 0.0;
+        if !front_face {
+            normal = -normal;
+        }
+        let material = materials[primitive.material];
+        color += attenuation * material.emit;
+        if depth == settings.max_depth {
+            break;
+        }
+        let scattered = scatter(material, ray.direction, normal, front_face);
+        if scattered.absorbed {
+            break;
+        }
+        attenuation *= scattered.attenuation;
+        ray = Ray(point, scattered.direction);
+        t_min = BOUNCE_T_MIN;
+    }
+    return Sample(vec4f(color, 1.0), object_id);
+}
+
+// Adds +-0.5 LSB of blue noise before the 8-bit store so smooth gradients don't band. The tile
+// is shifted every frame along the R2 sequence so the pattern doesn't sit still on screen.
+fn dither(color: vec3f, pixel: vec2u) -> vec3f {
+    let size = textureDimensions(blue_noise_tex);
+    let shift = fract(f32(settings.frame_index) * vec2f(0.7548777, 0.5698403));
+    let texel = (pixel + vec2u(shift * vec2f(size))) % size;
+    let noise = textureLoad(blue_noise_tex, texel, 0).r;
+    return color + (noise - 0.5) / 255.0;
+}
+
+// `pixel` is the position relative to the centre of the top-left pixel, in pixels.
+fn primary_ray(pixel: vec2f, size: vec2u) -> Ray {
+    let pixel_center = camera.pixel_00 + pixel.x * camera.pixel_du + pixel.y * camera.pixel_dv;
+    if camera.projection == PROJECTION_EQUIRECTANGULAR {
+        return Ray(camera.origin, equirectangular_direction(pixel + 0.5, size));
+    }
+    if camera.projection == PROJECTION_ORTHOGRAPHIC {
+        let forward = cross(normalize(camera.pixel_du), normalize(camera.pixel_dv));
+        return Ray(pixel_center, forward);
+    }
+    let lens = random_in_unit_disk();
+    let origin = camera.origin + lens.x * camera.defocus_u + lens.y * camera.defocus_v;
+    return Ray(origin, pixel_center - origin);
+}
+
+// Rewritten by `build_compute_pipeline` to match the selected `TileSize`.
+const TILE_WIDTH: u32 = 8u;
+const TILE_HEIGHT: u32 = 8u;
+
+@compute @workgroup_size(TILE_WIDTH, TILE_HEIGHT)
 fn main(
-    @builtin(global_invocation_id)
-    id: vec3u,
+    @builtin(workgroup_id) group: vec3u,
+    @builtin(num_workgroups) groups: vec3u,
+    @builtin(local_invocation_id) local: vec3u,
 ) {
-    let pixel_center = pixel_00_center + (f32(id.x) * viewport_du) + (f32(id.y) * viewport_dv);
-    let ray_direction = pixel_center - camera_origin;
+    let index = group.y * groups.x + group.x;
+    if index >= arrayLength(&tile_order) {
+        return;
+    }
+    let tile = tile_order[index];
+    let id = vec2u(tile & 0xffffu, tile >> 16u) * vec2u(TILE_WIDTH, TILE_HEIGHT) + local.xy;
+
+    let size = textureDimensions(output_tex);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+
+    rng_state = pcg_hash((id.y * size.x + id.x) ^ pcg_hash(settings.frame_index));
+
+    var color = vec3f(0.0);
+    var object_id = NO_OBJECT;
+    for (var i = 0u; i < settings.samples_per_pixel; i++) {
+        // A lone sample stays in the pixel centre until later frames are averaged in. Mirrored
+        // by `RenderSettings::jitters_samples`.
+        var offset = vec2f(0.0);
+        if settings.samples_per_pixel > 1u || settings.frame_count > 1u {
+            offset = vec2f(random_f32(), random_f32()) - 0.5;
+        }
+        let sample = trace(primary_ray(vec2f(id) + offset, size));
+        color += sample.color.rgb;
+        if i == 0u {
+            object_id = sample.object_id;
+        }
+    }
+    color /= f32(max(settings.samples_per_pixel, 1u));
+
+    // Mirrored by `RenderSettings::accumulation_weight`.
+    let weight = 1.0 / f32(max(settings.frame_count, 1u));
+    let average = mix(textureLoad(accumulation_in, id, 0).rgb, color, weight);
+    textureStore(accumulation_out, id, vec4f(average, 1.0));
 
-    let color = get_color(ray_direction);
+    var sample = Sample(vec4f(average, 1.0), object_id);
+    if settings.dither != 0u {
+        sample.color = vec4f(dither(sample.color.rgb, id), sample.color.a);
+    }
     // let rg = (ray_direction.xy + 1.0) * 0.5;
     // var b = 0.0;
     // if rg.x > 1.0 || rg.x < 0.0 {
@@ -57,5 +519,6 @@ fn main(
     // }
 
     // let color = vec4f(rg, b, 1.0);
-    textureStore(output_tex, id.xy, color);
+    textureStore(output_tex, id, sample.color);
+    textureStore(object_id_tex, id, vec4u(sample.object_id));
 }
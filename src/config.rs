@@ -0,0 +1,119 @@
+use std::{fs, io::ErrorKind};
+
+use serde::{Deserialize, Serialize};
+
+/// Launch settings read from a TOML file. Every key mirrors the command-line flag of the same
+/// name, and flags given on the command line take precedence over the file:
+///
+/// ```toml
+/// size = "1280x720"
+/// vsync = false
+/// adapter = "low-power"
+/// tile = "16x8"
+/// ```
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub size: Option<String>,
+    pub vsync: Option<bool>,
+    pub adapter: Option<String>,
+    pub tile: Option<String>,
+    pub tile_order: Option<String>,
+    pub aspect: Option<String>,
+    pub internal_res: Option<String>,
+    pub fps_cap: Option<u32>,
+    pub title: Option<String>,
+    pub projection: Option<String>,
+    pub near: Option<f32>,
+    pub samples: Option<u32>,
+    pub max_depth: Option<u32>,
+    pub tone_map: Option<String>,
+    pub lut: Option<String>,
+    pub environment: Option<String>,
+}
+
+impl Config {
+    /// Read from the working directory when `--config` isn't given; it's fine for it not to exist.
+    pub const DEFAULT_PATH: &'static str = "config.toml";
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn load_default() -> Result<Self, String> {
+        match fs::metadata(Self::DEFAULT_PATH) {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            _ => Self::load(Self::DEFAULT_PATH),
+        }
+    }
+
+    /// The settings as command-line arguments, to be parsed ahead of the real ones so that those
+    /// override them.
+    pub fn to_args(&self) -> Vec<String> {
+        let on_off = |on: bool| if on { "on" } else { "off" }.to_owned();
+        [
+            ("--size", self.size.clone()),
+            ("--vsync", self.vsync.map(on_off)),
+            ("--adapter", self.adapter.clone()),
+            ("--tile", self.tile.clone()),
+            ("--tile-order", self.tile_order.clone()),
+            ("--aspect", self.aspect.clone()),
+            ("--internal-res", self.internal_res.clone()),
+            ("--fps-cap", self.fps_cap.map(|cap| cap.to_string())),
+            ("--title", self.title.clone()),
+            ("--projection", self.projection.clone()),
+            ("--near", self.near.map(|near| near.to_string())),
+            ("--samples", self.samples.map(|samples| samples.to_string())),
+            ("--max-depth", self.max_depth.map(|depth| depth.to_string())),
+            ("--tone-map", self.tone_map.clone()),
+            ("--lut", self.lut.clone()),
+            ("--environment", self.environment.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(flag, value)| Some([flag.to_owned(), value?]))
+        .flatten()
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let config = Config {
+            size: Some("1280x720".to_owned()),
+            vsync: Some(false),
+            adapter: Some("low-power".to_owned()),
+            tile: Some("16x8".to_owned()),
+            fps_cap: Some(60),
+            near: Some(0.5),
+            samples: Some(4),
+            max_depth: Some(12),
+            tone_map: Some("aces".to_owned()),
+            ..Default::default()
+        };
+        let text = toml::to_string(&config).unwrap();
+        assert_eq!(toml::from_str::<Config>(&text).unwrap(), config);
+    }
+
+    #[test]
+    fn keys_are_kebab_case() {
+        let config: Config = toml::from_str("fps-cap = 30\ntile-order = \"morton\"").unwrap();
+        assert_eq!(config.fps_cap, Some(30));
+        assert_eq!(config.tile_order.as_deref(), Some("morton"));
+        assert!(toml::from_str::<Config>("fps_cap = 30").is_err());
+    }
+
+    #[test]
+    fn settings_become_flags() {
+        let config = Config {
+            vsync: Some(true),
+            samples: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(config.to_args(), ["--vsync", "on", "--samples", "4"]);
+    }
+}
@@ -0,0 +1,209 @@
+use wgpu::{
+    Adapter, BindGroupLayoutEntry, BindingType, BufferBindingType, SamplerBindingType,
+    ShaderStages, StorageTextureAccess, TextureSampleType,
+};
+
+use crate::{
+    camera::Camera,
+    display::DisplaySettings,
+    lut::ColorGrading,
+    scene::Scene,
+    settings::RenderSettings,
+    util::{COMPUTE_TEXTURE_LAYOUT_ENTRIES, RENDER_TEXTURE_LAYOUT_ENTRIES},
+};
+
+/// Bind group layouts of both pipelines, in the order they are set on the pipeline layout.
+pub fn layout_report() -> String {
+    let pipelines: [(&str, &[&[BindGroupLayoutEntry]]); 2] = [
+        (
+            "compute pipeline (compute.wgsl)",
+            &[
+                COMPUTE_TEXTURE_LAYOUT_ENTRIES,
+                Camera::LAYOUT_ENTRIES,
+                RenderSettings::LAYOUT_ENTRIES,
+                Scene::LAYOUT_ENTRIES,
+            ],
+        ),
+        (
+            "render pipeline (shader.wgsl)",
+            &[
+                RENDER_TEXTURE_LAYOUT_ENTRIES,
+                ColorGrading::LAYOUT_ENTRIES,
+                DisplaySettings::LAYOUT_ENTRIES,
+            ],
+        ),
+    ];
+
+    let mut report = String::new();
+    for (name, groups) in pipelines {
+        report += &format!("{}\n", name);
+        report += &format!(
+            "  {:<5}  {:<7}  {:<17}  {}\n",
+            "group", "binding", "visibility", "type"
+        );
+        for (group, entries) in groups.iter().enumerate() {
+            for entry in entries.iter() {
+                report += &format!(
+                    "  {:<5}  {:<7}  {:<17}  {}\n",
+                    group,
+                    entry.binding,
+                    visibility(entry.visibility),
+                    binding_type(&entry.ty)
+                );
+            }
+        }
+    }
+    report
+}
+
+/// What the adapter is and what it supports, for bug reports and before enabling optional
+/// features.
+pub fn adapter_report(adapter: &Adapter) -> String {
+    let info = adapter.get_info();
+    let limits = adapter.limits();
+
+    let mut report = String::from("adapter\n");
+    for (name, value) in [
+        ("name", info.name),
+        ("backend", format!("{:?}", info.backend)),
+        ("device type", format!("{:?}", info.device_type)),
+        ("vendor id", format!("{:#06x}", info.vendor)),
+        ("device id", format!("{:#06x}", info.device)),
+        ("driver", info.driver),
+        ("driver info", info.driver_info),
+    ] {
+        report += &format!("  {:<37}  {}\n", name, value);
+    }
+
+    report += "limits\n";
+    for (name, value) in [
+        (
+            "max_texture_dimension_2d",
+            limits.max_texture_dimension_2d.into(),
+        ),
+        (
+            "max_texture_dimension_3d",
+            limits.max_texture_dimension_3d.into(),
+        ),
+        ("max_bind_groups", limits.max_bind_groups.into()),
+        (
+            "max_uniform_buffer_binding_size",
+            limits.max_uniform_buffer_binding_size.into(),
+        ),
+        (
+            "max_storage_buffer_binding_size",
+            limits.max_storage_buffer_binding_size.into(),
+        ),
+        ("max_buffer_size", limits.max_buffer_size),
+        (
+            "max_compute_workgroup_size_x",
+            limits.max_compute_workgroup_size_x.into(),
+        ),
+        (
+            "max_compute_workgroup_size_y",
+            limits.max_compute_workgroup_size_y.into(),
+        ),
+        (
+            "max_compute_workgroup_size_z",
+            limits.max_compute_workgroup_size_z.into(),
+        ),
+        (
+            "max_compute_invocations_per_workgroup",
+            limits.max_compute_invocations_per_workgroup.into(),
+        ),
+        (
+            "max_compute_workgroups_per_dimension",
+            limits.max_compute_workgroups_per_dimension.into(),
+        ),
+        (
+            "max_compute_workgroup_storage_size",
+            limits.max_compute_workgroup_storage_size.into(),
+        ),
+    ] {
+        report += &format!("  {:<37}  {}\n", name, value);
+    }
+
+    report += "features\n";
+    let features = adapter.features();
+    if features.is_empty() {
+        report += "  (none)\n";
+    }
+    for (name, _) in features.iter_names() {
+        report += &format!("  {}\n", name.to_lowercase());
+    }
+    report
+}
+
+fn visibility(stages: ShaderStages) -> String {
+    [
+        (ShaderStages::VERTEX, "vertex"),
+        (ShaderStages::FRAGMENT, "fragment"),
+        (ShaderStages::COMPUTE, "compute"),
+    ]
+    .iter()
+    .filter(|(stage, _)| stages.contains(*stage))
+    .map(|(_, name)| *name)
+    .collect::<Vec<_>>()
+    .join("|")
+}
+
+fn binding_type(ty: &BindingType) -> String {
+    match ty {
+        BindingType::Buffer {
+            ty,
+            has_dynamic_offset,
+            ..
+        } => {
+            let kind = match ty {
+                BufferBindingType::Uniform => "uniform",
+                BufferBindingType::Storage { read_only: true } => "read-only storage",
+                BufferBindingType::Storage { read_only: false } => "storage",
+            };
+            let offset = if *has_dynamic_offset {
+                ", dynamic offset"
+            } else {
+                ""
+            };
+            format!("{} buffer{}", kind, offset)
+        }
+        BindingType::Sampler(kind) => match kind {
+            SamplerBindingType::Filtering => "sampler (filtering)".to_owned(),
+            SamplerBindingType::NonFiltering => "sampler (non-filtering)".to_owned(),
+            SamplerBindingType::Comparison => "sampler (comparison)".to_owned(),
+        },
+        BindingType::Texture {
+            sample_type,
+            view_dimension,
+            multisampled,
+        } => {
+            let sample_type = match sample_type {
+                TextureSampleType::Float { filterable: true } => "float",
+                TextureSampleType::Float { filterable: false } => "float (non-filterable)",
+                TextureSampleType::Depth => "depth",
+                TextureSampleType::Sint => "sint",
+                TextureSampleType::Uint => "uint",
+            };
+            let multisampled = if *multisampled { ", multisampled" } else { "" };
+            format!(
+                "texture {:?} {}{}",
+                view_dimension, sample_type, multisampled
+            )
+        }
+        BindingType::StorageTexture {
+            access,
+            format,
+            view_dimension,
+        } => {
+            let access = match access {
+                StorageTextureAccess::ReadOnly => "read-only",
+                StorageTextureAccess::WriteOnly => "write-only",
+                StorageTextureAccess::ReadWrite => "read-write",
+            };
+            format!(
+                "storage texture {:?} {:?} {}",
+                view_dimension, format, access
+            )
+        }
+        BindingType::AccelerationStructure => "acceleration structure".to_owned(),
+    }
+}
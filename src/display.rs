@@ -0,0 +1,205 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages,
+    Device, Queue, ShaderStages, Texture, TextureFormat, TextureSampleType, TextureViewDescriptor,
+    TextureViewDimension,
+};
+
+use crate::settings::ToneMap;
+
+/// What `frag_main` does to the linear colour before writing it, mirrored in `shader.wgsl`.
+const TRANSFER_NONE: u32 = 0;
+const TRANSFER_ENCODE: u32 = 1;
+const TRANSFER_DECODE: u32 = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct DisplayUniform {
+    transfer: u32,
+    tone_map: u32,
+    dither: u32,
+    target_srgb: u32,
+}
+
+/// How the linear image is turned into display values in the render pass, mirrored by
+/// `Display` in `shader.wgsl`.
+///
+/// With `gamma` on the output is sRGB-encoded, so 0.5 shows as a midtone of about 0.735. sRGB
+/// surfaces encode on write by themselves and other surfaces get the encoding in the shader. With
+/// it off the linear values reach the screen as they are, for comparing against linear
+/// references; on sRGB surfaces the shader then decodes to cancel the surface's encoding.
+///
+/// `tone_map` is applied before either, and follows `RenderSettings::tone_map`.
+///
+/// `dither` follows `RenderSettings::dither` and adds ±0.5 LSB of blue noise last, in the space
+/// the target quantises in, which is the sRGB encoding for sRGB surfaces. It doesn't count as a
+/// transform: the image `--direct-present` copies is dithered by the compute pass.
+pub struct DisplaySettings {
+    gamma: bool,
+    tone_map: ToneMap,
+    dither: bool,
+    target_srgb: bool,
+    buffer: Buffer,
+}
+
+impl DisplaySettings {
+    pub const LAYOUT_ENTRIES: &'static [BindGroupLayoutEntry] = &[
+        BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: false },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        },
+    ];
+
+    pub fn new(device: &Device, target_format: TextureFormat) -> Self {
+        let gamma = true;
+        let tone_map = ToneMap::default();
+        let dither = true;
+        let target_srgb = target_format.is_srgb();
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&DisplayUniform {
+                transfer: transfer(gamma, target_srgb),
+                tone_map: tone_map.as_uniform(),
+                dither: dither as u32,
+                target_srgb: target_srgb as u32,
+            }),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        Self {
+            gamma,
+            tone_map,
+            dither,
+            target_srgb,
+            buffer,
+        }
+    }
+
+    pub fn gamma(&self) -> bool {
+        self.gamma
+    }
+
+    pub fn set_gamma(&mut self, queue: &Queue, gamma: bool) {
+        self.gamma = gamma;
+        self.upload(queue);
+    }
+
+    /// Only writes the buffer when the curve changed, so it can be called every frame.
+    pub fn set_tone_map(&mut self, queue: &Queue, tone_map: ToneMap) {
+        if self.tone_map != tone_map {
+            self.tone_map = tone_map;
+            self.upload(queue);
+        }
+    }
+
+    /// Only writes the buffer when the setting changed, so it can be called every frame.
+    pub fn set_dither(&mut self, queue: &Queue, dither: bool) {
+        if self.dither != dither {
+            self.dither = dither;
+            self.upload(queue);
+        }
+    }
+
+    /// Whether the shader changes the colours, so they can't be copied to the surface as they are.
+    pub fn transforms(&self) -> bool {
+        transfer(self.gamma, self.target_srgb) != TRANSFER_NONE || self.tone_map != ToneMap::None
+    }
+
+    pub fn bind_group_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: Self::LAYOUT_ENTRIES,
+        })
+    }
+
+    pub fn bind_group(&self, device: &Device, blue_noise_texture: &Texture) -> BindGroup {
+        let blue_noise_view = blue_noise_texture.create_view(&TextureViewDescriptor::default());
+        device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &Self::bind_group_layout(device),
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Buffer(self.buffer.as_entire_buffer_binding()),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&blue_noise_view),
+                },
+            ],
+        })
+    }
+
+    fn upload(&self, queue: &Queue) {
+        let uniform = DisplayUniform {
+            transfer: transfer(self.gamma, self.target_srgb),
+            tone_map: self.tone_map.as_uniform(),
+            dither: self.dither as u32,
+            target_srgb: self.target_srgb as u32,
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+}
+
+/// The sRGB transfer function for one channel, as `srgb_encode` in `shader.wgsl` applies it.
+#[allow(dead_code)]
+pub fn srgb_encode(linear: f32) -> f32 {
+    let c = linear.clamp(0.0, 1.0);
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn transfer(gamma: bool, target_srgb: bool) -> u32 {
+    match (gamma, target_srgb) {
+        (true, false) => TRANSFER_ENCODE,
+        (false, true) => TRANSFER_DECODE,
+        _ => TRANSFER_NONE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_encodes_a_linear_midtone() {
+        assert!((srgb_encode(0.5) - 0.735).abs() < 1e-3);
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn srgb_is_continuous_at_the_linear_segment() {
+        let knee = 0.0031308;
+        assert!((srgb_encode(knee) - srgb_encode(knee + 1e-7)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn transfer_encodes_once() {
+        assert_eq!(transfer(true, false), TRANSFER_ENCODE);
+        assert_eq!(transfer(true, true), TRANSFER_NONE);
+        assert_eq!(transfer(false, false), TRANSFER_NONE);
+        assert_eq!(transfer(false, true), TRANSFER_DECODE);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{ray::Ray, util::Vec3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Aabb {
+    /// The box spanned by two opposite corners, in any order.
+    pub fn new(a: Vec3, b: Vec3) -> Self {
+        Self {
+            min: Vec3(a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)),
+            max: Vec3(a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)),
+        }
+    }
+
+    /// Bounds of unbounded geometry such as planes.
+    pub fn infinite() -> Self {
+        Self {
+            min: Vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+            max: Vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        }
+    }
+
+    pub fn surrounding_box(a: &Aabb, b: &Aabb) -> Aabb {
+        Aabb::new(
+            Vec3(
+                a.min.0.min(b.min.0),
+                a.min.1.min(b.min.1),
+                a.min.2.min(b.min.2),
+            ),
+            Vec3(
+                a.max.0.max(b.max.0),
+                a.max.1.max(b.max.1),
+                a.max.2.max(b.max.2),
+            ),
+        )
+    }
+
+    pub fn min(&self) -> Vec3 {
+        self.min
+    }
+
+    pub fn max(&self) -> Vec3 {
+        self.max
+    }
+
+    /// Slab test. A zero direction component makes `1.0 / direction` infinite, which still
+    /// classifies the ray correctly against that slab unless the origin lies exactly on one of
+    /// its planes; the NaN that produces is ignored by `f32::max`/`min`.
+    pub fn hit(&self, ray: &Ray, mut t_min: f32, mut t_max: f32) -> bool {
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse_direction;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse_direction;
+            if inverse_direction < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Vec3(1.0, 1.0, 1.0), Vec3(-1.0, -1.0, -1.0))
+    }
+
+    #[test]
+    fn new_orders_corners() {
+        let aabb = unit_box();
+        assert_eq!(aabb.min(), Vec3(-1.0, -1.0, -1.0));
+        assert_eq!(aabb.max(), Vec3(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn hit_between_enter_and_exit() {
+        // Enters at t = 4, exits at t = 6.
+        let ray = Ray::new(Vec3(0.0, 0.0, 5.0), -Vec3::k());
+        assert!(unit_box().hit(&ray, 0.0, f32::INFINITY));
+        assert!(!unit_box().hit(&ray, 0.0, 3.9));
+        assert!(!unit_box().hit(&ray, 6.1, f32::INFINITY));
+        assert!(unit_box().hit(&ray, 5.0, 5.5));
+    }
+
+    #[test]
+    fn hit_from_inside() {
+        let ray = Ray::new(Vec3::origin(), Vec3(1.0, 2.0, 3.0));
+        assert!(unit_box().hit(&ray, 0.0, f32::INFINITY));
+    }
+
+    #[test]
+    fn miss_beside() {
+        let ray = Ray::new(Vec3(2.0, 0.0, 5.0), -Vec3::k());
+        assert!(!unit_box().hit(&ray, 0.0, f32::INFINITY));
+
+        let diagonal = Ray::new(Vec3(0.0, 3.0, 0.0), Vec3(1.0, -0.5, 0.0));
+        assert!(!unit_box().hit(&diagonal, 0.0, f32::INFINITY));
+    }
+
+    #[test]
+    fn surrounding_box_covers_both() {
+        let a = Aabb::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 1.0, 1.0));
+        let b = Aabb::new(Vec3(-2.0, 0.5, 0.5), Vec3(0.5, 3.0, 0.5));
+        let union = Aabb::surrounding_box(&a, &b);
+        assert_eq!(union.min(), Vec3(-2.0, 0.0, 0.0));
+        assert_eq!(union.max(), Vec3(1.0, 3.0, 1.0));
+        assert_eq!(Aabb::surrounding_box(&b, &a), union);
+    }
+
+    #[test]
+    fn infinite_box_is_always_hit() {
+        let ray = Ray::new(Vec3(1e6, -1e6, 0.0), Vec3(0.3, 0.1, -1.0));
+        assert!(Aabb::infinite().hit(&ray, 0.0, f32::INFINITY));
+    }
+}
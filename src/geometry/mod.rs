@@ -1,5 +1,109 @@
-use crate::util::Vec3;
-
+pub mod aabb;
+pub mod plane;
 pub mod sphere;
+pub mod triangle;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{ray::Ray, util::Vec3};
+
+use aabb::Aabb;
+
+#[derive(Clone, Copy, Debug)]
+pub struct HitRecord {
+    pub point: Vec3,
+    /// Always faces against the ray; `front_face` tells whether that is the outward normal.
+    pub normal: Vec3,
+    pub t: f32,
+    pub front_face: bool,
+}
+
+impl HitRecord {
+    pub fn new(ray: &Ray, t: f32, outward_normal: Vec3) -> Self {
+        let front_face = ray.direction.dot(outward_normal) < 0.0;
+        Self {
+            point: ray.at(t),
+            normal: if front_face {
+                outward_normal
+            } else {
+                -outward_normal
+            },
+            t,
+            front_face,
+        }
+    }
+}
+
+/// `Primitive` in `compute.wgsl`. Every kind of geometry shares this layout, so the shader can
+/// keep a whole scene in one array: spheres use `a` as the center plus `radius`, planes use `a`
+/// as a point on the plane and `b` as the normal, and triangles use `a`, `b`, `c` as vertices.
+/// `material` indexes the scene's materials and is filled in by the scene.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct GpuPrimitive {
+    pub a: [f32; 3],
+    pub kind: u32,
+    pub b: [f32; 3],
+    pub radius: f32,
+    pub c: [f32; 3],
+    pub material: u32,
+}
+
+impl GpuPrimitive {
+    pub const SPHERE: u32 = 0;
+    pub const PLANE: u32 = 1;
+    pub const TRIANGLE: u32 = 2;
+}
+
+/// Scenes and the BVH store geometry as `Box<dyn Geometry>`, so the trait has to stay object
+/// safe: no generic methods and no `Self` in argument or return types.
+pub trait Geometry {
+    /// The closest intersection with `t` in the open interval (`t_min`, `t_max`).
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+
+    fn bounding_box(&self) -> Aabb;
+
+    fn to_gpu(&self) -> GpuPrimitive;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{plane::Plane, sphere::Sphere, triangle::Triangle};
+
+    #[test]
+    fn mixed_primitives_share_a_list() {
+        let objects: Vec<Box<dyn Geometry>> = vec![
+            Box::new(Sphere::new(Vec3(0.0, 0.0, -2.0), 0.5)),
+            Box::new(Plane::new(Vec3(0.0, -1.0, 0.0), Vec3::j())),
+            Box::new(Triangle::new(
+                Vec3(-1.0, -1.0, -1.0),
+                Vec3(1.0, -1.0, -1.0),
+                Vec3(0.0, 1.0, -1.0),
+            )),
+        ];
+
+        let kinds: Vec<_> = objects.iter().map(|object| object.to_gpu().kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                GpuPrimitive::SPHERE,
+                GpuPrimitive::PLANE,
+                GpuPrimitive::TRIANGLE
+            ]
+        );
+
+        // Straight ahead the triangle is in front of the sphere; the plane is below both.
+        let ray = Ray::new(Vec3::origin(), -Vec3::k());
+        let hits: Vec<_> = objects
+            .iter()
+            .map(|object| object.hit(&ray, 0.001, f32::INFINITY).map(|hit| hit.t))
+            .collect();
+        assert_eq!(hits, [Some(1.5), None, Some(1.0)]);
 
-pub trait Geometry {}
+        for object in &objects {
+            let bounds = object.bounding_box();
+            assert!(bounds.min().0 <= bounds.max().0);
+        }
+    }
+}
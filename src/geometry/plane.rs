@@ -0,0 +1,92 @@
+use crate::{ray::Ray, util::Vec3};
+
+use super::{aabb::Aabb, Geometry, GpuPrimitive, HitRecord};
+
+/// Infinite plane through `point`. Its front face is the side `normal` points to.
+pub struct Plane {
+    point: Vec3,
+    normal: Vec3,
+}
+
+#[allow(dead_code)]
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3) -> Self {
+        Self {
+            point,
+            normal: normal.normalize(),
+        }
+    }
+
+    pub fn point(&self) -> Vec3 {
+        self.point
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+}
+
+impl Geometry for Plane {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = self.normal.dot(self.point - ray.origin) / denominator;
+        (t_min < t && t < t_max).then(|| HitRecord::new(ray, t, self.normal))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::infinite()
+    }
+
+    fn to_gpu(&self) -> GpuPrimitive {
+        GpuPrimitive {
+            a: self.point.as_array(),
+            kind: GpuPrimitive::PLANE,
+            b: self.normal.as_array(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ground() -> Plane {
+        Plane::new(Vec3::origin(), Vec3(0.0, 2.0, 0.0))
+    }
+
+    #[test]
+    fn hit_from_above_is_front_face() {
+        let ray = Ray::new(Vec3(1.0, 2.0, 0.0), -Vec3::j());
+        let hit = ground().hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert_eq!(hit.t, 2.0);
+        assert_eq!(hit.point, Vec3(1.0, 0.0, 0.0));
+        assert_eq!(hit.normal, Vec3::j());
+        assert!(hit.front_face);
+    }
+
+    #[test]
+    fn hit_from_below_flips_the_normal() {
+        let ray = Ray::new(Vec3(0.0, -3.0, 0.0), Vec3::j());
+        let hit = ground().hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert_eq!(hit.t, 3.0);
+        assert_eq!(hit.normal, -Vec3::j());
+        assert!(!hit.front_face);
+    }
+
+    #[test]
+    fn parallel_ray_misses() {
+        let ray = Ray::new(Vec3(0.0, 1.0, 0.0), Vec3::i());
+        assert!(ground().hit(&ray, 0.001, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn receding_ray_misses() {
+        let ray = Ray::new(Vec3(0.0, 1.0, 0.0), Vec3::j());
+        assert!(ground().hit(&ray, 0.001, f32::INFINITY).is_none());
+    }
+}
@@ -1,10 +1,138 @@
-use crate::util::Vec3;
+use crate::{ray::Ray, util::Vec3};
 
-use super::Geometry;
+use super::{aabb::Aabb, Geometry, GpuPrimitive, HitRecord};
 
 pub struct Sphere {
-    certre: Vec3,
+    center: Vec3,
     radius: f32,
 }
 
-impl Geometry for Sphere {}
+impl Sphere {
+    /// A radius that isn't positive is a bug in the caller. Release builds clamp it to
+    /// `f32::EPSILON` rather than 0, since `hit` divides by the radius to get the normal.
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        debug_assert!(
+            radius > 0.0,
+            "sphere radius must be positive, got {}",
+            radius
+        );
+        Self {
+            center,
+            radius: radius.max(f32::EPSILON),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn center(&self) -> Vec3 {
+        self.center
+    }
+
+    #[allow(dead_code)]
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+}
+
+impl Geometry for Sphere {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let oc = self.center - ray.origin;
+        let a = ray.direction.length_squared();
+        let h = ray.direction.dot(oc);
+        let c = oc.length_squared() - self.radius * self.radius;
+        let discriminant = h * h - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrt_d = discriminant.sqrt();
+        let t = [(h - sqrt_d) / a, (h + sqrt_d) / a]
+            .into_iter()
+            .find(|t| t_min < *t && *t < t_max)?;
+        let outward_normal = (ray.at(t) - self.center) / self.radius;
+        Some(HitRecord::new(ray, t, outward_normal))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = Vec3(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+
+    fn to_gpu(&self) -> GpuPrimitive {
+        GpuPrimitive {
+            a: self.center.as_array(),
+            kind: GpuPrimitive::SPHERE,
+            radius: self.radius,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_sphere_ahead() -> Sphere {
+        Sphere::new(Vec3(0.0, 0.0, -5.0), 1.0)
+    }
+
+    #[test]
+    fn hit_head_on() {
+        let ray = Ray::new(Vec3::origin(), -Vec3::k());
+        let hit = unit_sphere_ahead().hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert_eq!(hit.t, 4.0);
+        assert_eq!(hit.point, Vec3(0.0, 0.0, -4.0));
+        assert_eq!(hit.normal, Vec3::k());
+        assert!(hit.front_face);
+    }
+
+    #[test]
+    fn graze_touches_the_silhouette() {
+        let ray = Ray::new(Vec3(0.0, 1.0, 0.0), -Vec3::k());
+        let hit = unit_sphere_ahead().hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert_eq!(hit.t, 5.0);
+        assert_eq!(hit.point, Vec3(0.0, 1.0, -5.0));
+        assert_eq!(hit.normal.dot(ray.direction), 0.0);
+    }
+
+    #[test]
+    fn miss_beside() {
+        let ray = Ray::new(Vec3(0.0, 1.5, 0.0), -Vec3::k());
+        assert!(unit_sphere_ahead()
+            .hit(&ray, 0.001, f32::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn miss_behind() {
+        let ray = Ray::new(Vec3::origin(), Vec3::k());
+        assert!(unit_sphere_ahead()
+            .hit(&ray, 0.001, f32::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn hit_from_inside_is_back_face() {
+        let ray = Ray::new(Vec3(0.0, 0.0, -5.0), -Vec3::k());
+        let hit = unit_sphere_ahead().hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert_eq!(hit.t, 1.0);
+        assert!(!hit.front_face);
+        assert_eq!(hit.normal, Vec3::k());
+    }
+
+    #[test]
+    fn new_keeps_center_and_radius() {
+        let sphere = Sphere::new(Vec3(1.0, 2.0, 3.0), 0.5);
+        assert_eq!(sphere.center(), Vec3(1.0, 2.0, 3.0));
+        assert_eq!(sphere.radius(), 0.5);
+    }
+
+    #[test]
+    fn tiny_radius_still_has_a_normal() {
+        let sphere = Sphere::new(Vec3::origin(), 1e-12);
+        assert_eq!(sphere.radius(), f32::EPSILON);
+
+        let ray = Ray::new(Vec3(0.0, 0.0, 1.0), -Vec3::k());
+        let hit = sphere.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!(hit.normal.as_array().iter().all(|c| c.is_finite()));
+    }
+}
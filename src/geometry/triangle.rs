@@ -0,0 +1,137 @@
+use crate::{ray::Ray, util::Vec3};
+
+use super::{aabb::Aabb, Geometry, GpuPrimitive, HitRecord};
+
+/// Both sides are hit; the outward normal, and so `front_face`, follows the counter-clockwise
+/// winding of `v0`, `v1`, `v2`.
+pub struct Triangle {
+    v0: Vec3,
+    v1: Vec3,
+    v2: Vec3,
+    normal: Vec3,
+}
+
+#[allow(dead_code)]
+impl Triangle {
+    pub fn new(v0: Vec3, v1: Vec3, v2: Vec3) -> Self {
+        Self {
+            v0,
+            v1,
+            v2,
+            normal: (v1 - v0).cross(v2 - v0).normalize(),
+        }
+    }
+
+    pub fn vertices(&self) -> [Vec3; 3] {
+        [self.v0, self.v1, self.v2]
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    /// Möller–Trumbore: `t` along the ray and the barycentric weights `u`, `v` of `v1` and `v2`
+    /// at the hit point (`v0` gets `1 - u - v`).
+    pub fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+        let edge1 = self.v1 - self.v0;
+        let edge2 = self.v2 - self.v0;
+        let p = ray.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        if determinant.abs() < 1e-8 {
+            return None;
+        }
+        let inverse_determinant = 1.0 / determinant;
+
+        let s = ray.origin - self.v0;
+        let u = s.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(edge1);
+        let v = ray.direction.dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(q) * inverse_determinant;
+        (t_min < t && t < t_max).then_some((t, u, v))
+    }
+}
+
+impl Geometry for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let (t, _, _) = self.intersect(ray, t_min, t_max)?;
+        Some(HitRecord::new(ray, t, self.normal))
+    }
+
+    /// Padded so that an axis-aligned triangle doesn't get a box of zero thickness.
+    fn bounding_box(&self) -> Aabb {
+        const PADDING: f32 = 1e-4;
+        let bounds =
+            Aabb::surrounding_box(&Aabb::new(self.v0, self.v1), &Aabb::new(self.v2, self.v2));
+        let padding = Vec3(PADDING, PADDING, PADDING);
+        Aabb::new(bounds.min() - padding, bounds.max() + padding)
+    }
+
+    fn to_gpu(&self) -> GpuPrimitive {
+        GpuPrimitive {
+            a: self.v0.as_array(),
+            kind: GpuPrimitive::TRIANGLE,
+            b: self.v1.as_array(),
+            c: self.v2.as_array(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counter-clockwise seen from +z, so its normal is +z.
+    fn triangle() -> Triangle {
+        Triangle::new(
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(3.0, 0.0, -1.0),
+            Vec3(0.0, 3.0, -1.0),
+        )
+    }
+
+    #[test]
+    fn centroid_has_equal_weights() {
+        let ray = Ray::new(Vec3(1.0, 1.0, 0.0), -Vec3::k());
+        let (t, u, v) = triangle().intersect(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((t - 1.0).abs() < 1e-6);
+        assert!((u - 1.0 / 3.0).abs() < 1e-6);
+        assert!((v - 1.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn weights_pick_out_the_vertices() {
+        let (_, u, v) = triangle()
+            .intersect(
+                &Ray::new(Vec3(3.0, 0.0, 0.0), -Vec3::k()),
+                0.001,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert_eq!((u, v), (1.0, 0.0));
+    }
+
+    #[test]
+    fn miss_past_the_hypotenuse() {
+        let ray = Ray::new(Vec3(2.0, 2.0, 0.0), -Vec3::k());
+        assert!(triangle().intersect(&ray, 0.001, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn normal_follows_winding() {
+        assert_eq!(triangle().normal(), Vec3::k());
+
+        let from_behind = Ray::new(Vec3(1.0, 1.0, -2.0), Vec3::k());
+        let hit = triangle().hit(&from_behind, 0.001, f32::INFINITY).unwrap();
+        assert!(!hit.front_face);
+        assert_eq!(hit.normal, -Vec3::k());
+    }
+}
@@ -0,0 +1,87 @@
+use winit::keyboard::KeyCode;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    ToggleHelp,
+    ToggleAspectLock,
+    ToggleProjection,
+    ToggleColorGrading,
+    ToggleGamma,
+    CycleToneMap,
+    ToggleDither,
+    ToggleTileOrder,
+    PrintFrameStats,
+}
+
+pub struct KeyBinding {
+    pub keys: &'static [KeyCode],
+    pub label: &'static str,
+    pub action: Action,
+    pub description: &'static str,
+}
+
+/// Every hotkey `App::key_pressed` reacts to. The help overlay is generated from this table, so
+/// a binding added here shows up there too.
+pub const KEY_BINDINGS: &[KeyBinding] = &[
+    KeyBinding {
+        keys: &[KeyCode::F1, KeyCode::KeyH],
+        label: "F1/H",
+        action: Action::ToggleHelp,
+        description: "toggle this help",
+    },
+    KeyBinding {
+        keys: &[KeyCode::KeyL],
+        label: "L",
+        action: Action::ToggleAspectLock,
+        description: "lock aspect ratio",
+    },
+    KeyBinding {
+        keys: &[KeyCode::KeyP],
+        label: "P",
+        action: Action::ToggleProjection,
+        description: "360 projection",
+    },
+    KeyBinding {
+        keys: &[KeyCode::KeyG],
+        label: "G",
+        action: Action::ToggleColorGrading,
+        description: "colour grading LUT",
+    },
+    KeyBinding {
+        keys: &[KeyCode::KeyE],
+        label: "E",
+        action: Action::ToggleGamma,
+        description: "sRGB gamma encoding",
+    },
+    KeyBinding {
+        keys: &[KeyCode::KeyM],
+        label: "M",
+        action: Action::CycleToneMap,
+        description: "tone mapping",
+    },
+    KeyBinding {
+        keys: &[KeyCode::KeyD],
+        label: "D",
+        action: Action::ToggleDither,
+        description: "blue-noise dithering",
+    },
+    KeyBinding {
+        keys: &[KeyCode::KeyO],
+        label: "O",
+        action: Action::ToggleTileOrder,
+        description: "Morton tile order",
+    },
+    KeyBinding {
+        keys: &[KeyCode::KeyT],
+        label: "T",
+        action: Action::PrintFrameStats,
+        description: "print frame-time stats",
+    },
+];
+
+pub fn action_for(key: KeyCode) -> Option<Action> {
+    KEY_BINDINGS
+        .iter()
+        .find(|binding| binding.keys.contains(&key))
+        .map(|binding| binding.action)
+}
@@ -0,0 +1,440 @@
+use std::{fs, path::Path};
+
+use bytemuck::{Pod, Zeroable};
+use half::f16;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt, TextureDataOrder},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
+    BufferBindingType, BufferUsages, Device, Extent3d, FilterMode, Queue, Sampler,
+    SamplerBindingType, SamplerDescriptor, ShaderStages, Texture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureViewDescriptor,
+    TextureViewDimension,
+};
+
+/// 1D LUTs are baked into a 3D LUT of this size; each axis only depends on its own channel, so
+/// trilinear filtering reproduces the 1D curve's linear interpolation exactly.
+const BAKED_1D_SIZE: u32 = 33;
+const MAX_SIZE: u32 = 65;
+
+/// A colour grading lookup table read from an Adobe/Resolve `.cube` file.
+///
+/// The file is plain text: `#` comments, optional `TITLE "..."`, one of `LUT_3D_SIZE N` or
+/// `LUT_1D_SIZE N`, optional `DOMAIN_MIN r g b` / `DOMAIN_MAX r g b` (default 0 and 1), then
+/// `N³` (or `N`) lines of `r g b` with red changing fastest, then green, then blue. 3D sizes
+/// from 2 up to 65 are accepted, which covers the common 17³, 33³ and 65³ tables.
+///
+/// Like most grades exported from Resolve or Photoshop, the table is expected to map sRGB-encoded
+/// colours to sRGB-encoded colours: `shader.wgsl` encodes the tone-mapped image before the lookup
+/// and decodes the result, so the domain refers to encoded values.
+#[derive(Debug, Clone)]
+pub struct Lut {
+    size: u32,
+    texels: Vec<[f32; 3]>,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+}
+
+impl Lut {
+    pub fn identity(size: u32) -> Self {
+        let step = |i: u32| i as f32 / (size - 1) as f32;
+        let texels = (0..size.pow(3))
+            .map(|i| {
+                [
+                    step(i % size),
+                    step(i / size % size),
+                    step(i / (size * size)),
+                ]
+            })
+            .collect();
+        Self {
+            size,
+            texels,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut size_3d = None;
+        let mut size_1d = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut entries = vec![];
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+            let numbers = || -> Result<Vec<f32>, String> {
+                line.split_whitespace()
+                    .skip(usize::from(
+                        keyword.starts_with(|c: char| c.is_ascii_alphabetic()),
+                    ))
+                    .map(|word| word.parse().map_err(|_| error("expected a number")))
+                    .collect()
+            };
+            let triple = || -> Result<[f32; 3], String> {
+                numbers()?
+                    .try_into()
+                    .map_err(|_| error("expected three numbers"))
+            };
+
+            match keyword {
+                "TITLE" => {}
+                "LUT_3D_SIZE" | "LUT_1D_SIZE" => {
+                    let size = words
+                        .next()
+                        .and_then(|size| size.parse::<u32>().ok())
+                        .filter(|size| *size >= 2)
+                        .ok_or_else(|| error("invalid LUT size"))?;
+                    if keyword == "LUT_3D_SIZE" {
+                        size_3d = Some(size);
+                    } else {
+                        size_1d = Some(size);
+                    }
+                }
+                "DOMAIN_MIN" => domain_min = triple()?,
+                "DOMAIN_MAX" => domain_max = triple()?,
+                "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                    let [min, max]: [f32; 2] = numbers()?
+                        .try_into()
+                        .map_err(|_| error("expected two numbers"))?;
+                    domain_min = [min; 3];
+                    domain_max = [max; 3];
+                }
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    return Err(error(&format!("unknown keyword `{}`", keyword)))
+                }
+                _ => entries.push(triple()?),
+            }
+        }
+
+        if (0..3).any(|i| domain_max[i] <= domain_min[i]) {
+            return Err("DOMAIN_MAX must be greater than DOMAIN_MIN".to_owned());
+        }
+
+        let (size, texels) = match (size_3d, size_1d) {
+            (Some(size), None) => {
+                if size > MAX_SIZE {
+                    return Err(format!(
+                        "3D LUTs larger than {}³ are not supported",
+                        MAX_SIZE
+                    ));
+                }
+                (size, entries)
+            }
+            (None, Some(size)) => {
+                if entries.len() != size as usize {
+                    return Err(format!(
+                        "expected {} entries, found {}",
+                        size,
+                        entries.len()
+                    ));
+                }
+                (BAKED_1D_SIZE, bake_1d(&entries))
+            }
+            (Some(_), Some(_)) => return Err("both LUT_1D_SIZE and LUT_3D_SIZE given".to_owned()),
+            (None, None) => return Err("missing LUT_3D_SIZE or LUT_1D_SIZE".to_owned()),
+        };
+        if texels.len() != size.pow(3) as usize {
+            return Err(format!(
+                "expected {} entries, found {}",
+                size.pow(3),
+                texels.len()
+            ));
+        }
+
+        Ok(Self {
+            size,
+            texels,
+            domain_min,
+            domain_max,
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+fn bake_1d(curve: &[[f32; 3]]) -> Vec<[f32; 3]> {
+    let sample = |channel: usize, t: f32| {
+        let x = t * (curve.len() - 1) as f32;
+        let i = (x.floor() as usize).min(curve.len() - 2);
+        let f = x - i as f32;
+        curve[i][channel] * (1.0 - f) + curve[i + 1][channel] * f
+    };
+    let step = |i: u32| i as f32 / (BAKED_1D_SIZE - 1) as f32;
+
+    (0..BAKED_1D_SIZE.pow(3))
+        .map(|i| {
+            [
+                sample(0, step(i % BAKED_1D_SIZE)),
+                sample(1, step(i / BAKED_1D_SIZE % BAKED_1D_SIZE)),
+                sample(2, step(i / (BAKED_1D_SIZE * BAKED_1D_SIZE))),
+            ]
+        })
+        .collect()
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct LutParams {
+    domain_min: [f32; 3],
+    enabled: u32,
+    domain_max: [f32; 3],
+    size: f32,
+}
+
+/// GPU side of the optional grading LUT applied by `shader.wgsl`. Without a LUT a 2³ identity
+/// table is bound and the pass is disabled, so the output is untouched.
+pub struct ColorGrading {
+    texture: Texture,
+    sampler: Sampler,
+    params: LutParams,
+    params_buffer: Buffer,
+    loaded: bool,
+}
+
+impl ColorGrading {
+    pub const LAYOUT_ENTRIES: &'static [BindGroupLayoutEntry] = &[
+        BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D3,
+                multisampled: false,
+            },
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 2,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ];
+
+    pub fn new(device: &Device, queue: &Queue, lut: Option<Lut>) -> Self {
+        let loaded = lut.is_some();
+        let lut = lut.unwrap_or_else(|| Lut::identity(2));
+
+        let texels: Vec<f16> = lut
+            .texels
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 1.0])
+            .map(f16::from_f32)
+            .collect();
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                size: Extent3d {
+                    width: lut.size,
+                    height: lut.size,
+                    depth_or_array_layers: lut.size,
+                },
+                format: TextureFormat::Rgba16Float,
+                usage: TextureUsages::TEXTURE_BINDING,
+                label: None,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D3,
+                view_formats: &[TextureFormat::Rgba16Float],
+            },
+            TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(&texels),
+        );
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let params = LutParams {
+            domain_min: lut.domain_min,
+            enabled: loaded as u32,
+            domain_max: lut.domain_max,
+            size: lut.size as f32,
+        };
+        let params_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&params),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        Self {
+            texture,
+            sampler,
+            params,
+            params_buffer,
+            loaded,
+        }
+    }
+
+    pub fn bind_group_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: Self::LAYOUT_ENTRIES,
+        })
+    }
+
+    pub fn bind_group(&self, device: &Device) -> BindGroup {
+        let view = self.texture.create_view(&TextureViewDescriptor::default());
+        device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &Self::bind_group_layout(device),
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Buffer(
+                        self.params_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+            ],
+        })
+    }
+
+    pub fn loaded(&self) -> bool {
+        self.loaded
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.params.enabled != 0
+    }
+
+    /// Has no effect when no LUT was loaded.
+    pub fn set_enabled(&mut self, queue: &Queue, enabled: bool) {
+        self.params.enabled = (enabled && self.loaded) as u32;
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY_2: &str = "\
+TITLE \"identity\"
+# red changes fastest
+LUT_3D_SIZE 2
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1
+";
+
+    #[test]
+    fn parses_a_3d_identity() {
+        let lut = Lut::parse(IDENTITY_2).unwrap();
+        let identity = Lut::identity(2);
+        assert_eq!(lut.size(), 2);
+        assert_eq!(lut.texels, identity.texels);
+        assert_eq!(lut.domain_min, [0.0; 3]);
+        assert_eq!(lut.domain_max, [1.0; 3]);
+    }
+
+    #[test]
+    fn bakes_a_1d_table_into_3d() {
+        let lut = Lut::parse("LUT_1D_SIZE 2\n0 0 0\n1 0.5 0.25\n").unwrap();
+        assert_eq!(lut.size(), BAKED_1D_SIZE);
+        assert_eq!(lut.texels.len(), BAKED_1D_SIZE.pow(3) as usize);
+
+        // Each channel follows its own curve along its own axis only.
+        let n = BAKED_1D_SIZE as usize;
+        let at = |r: usize, g: usize, b: usize| lut.texels[r + n * (g + n * b)];
+        assert_eq!(at(0, 0, 0), [0.0, 0.0, 0.0]);
+        assert_eq!(at(n - 1, 0, 0), [1.0, 0.0, 0.0]);
+        assert_eq!(at(0, n - 1, 0), [0.0, 0.5, 0.0]);
+        assert_eq!(at(n - 1, n - 1, n - 1), [1.0, 0.5, 0.25]);
+        assert_eq!(at(n / 2, n / 2, n / 2), [0.5, 0.25, 0.125]);
+    }
+
+    #[test]
+    fn reads_the_domain() {
+        let source = IDENTITY_2.replace(
+            "LUT_3D_SIZE 2",
+            "LUT_3D_SIZE 2\nDOMAIN_MIN 0 0.1 0.2\nDOMAIN_MAX 1 2 4",
+        );
+        let lut = Lut::parse(&source).unwrap();
+        assert_eq!(lut.domain_min, [0.0, 0.1, 0.2]);
+        assert_eq!(lut.domain_max, [1.0, 2.0, 4.0]);
+
+        let source = IDENTITY_2.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE -1 2");
+        let lut = Lut::parse(&source).unwrap();
+        assert_eq!(lut.domain_min, [-1.0; 3]);
+        assert_eq!(lut.domain_max, [2.0; 3]);
+
+        let source = IDENTITY_2.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nDOMAIN_MAX 1 0 1");
+        assert!(Lut::parse(&source).is_err());
+    }
+
+    #[test]
+    fn rejects_a_wrong_entry_count() {
+        let missing = IDENTITY_2.trim_end().trim_end_matches("1 1 1");
+        assert_eq!(
+            Lut::parse(missing).unwrap_err(),
+            "expected 8 entries, found 7"
+        );
+        assert_eq!(
+            Lut::parse("LUT_1D_SIZE 3\n0 0 0\n1 1 1\n").unwrap_err(),
+            "expected 3 entries, found 2"
+        );
+    }
+
+    #[test]
+    fn rejects_sizes_above_65() {
+        assert_eq!(
+            Lut::parse("LUT_3D_SIZE 66\n").unwrap_err(),
+            "3D LUTs larger than 65³ are not supported"
+        );
+        assert!(Lut::parse("LUT_3D_SIZE 1\n").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_keywords() {
+        let source = IDENTITY_2.replace("TITLE \"identity\"", "LUT_4D_SIZE 2");
+        assert_eq!(
+            Lut::parse(&source).unwrap_err(),
+            "line 1: unknown keyword `LUT_4D_SIZE`"
+        );
+    }
+}
@@ -1,72 +1,188 @@
 use std::{
-    cell::RefCell,
     sync::{Arc, OnceLock},
+    time::{Duration, Instant},
 };
 
-use camera::Camera;
-use geometry::Geometry;
+use benchmark::{FrameStats, SweepStep, TileSweep};
+use camera::{Camera, ProjectionMode};
+use cli::Args;
+use display::DisplaySettings;
+use geometry::sphere::Sphere;
+use input::{Action, KEY_BINDINGS};
+use lut::{ColorGrading, Lut};
+use material::{Dielectric, Lambertian, Metal};
+use overlay::TextOverlay;
+use scene::Scene;
+use schedule::{TileOrder, TileSchedule};
+use settings::{RenderSettings, ToneMap};
 use util::{
-    build_compute_pipeline, build_render_pipeline, build_texture, texture_bind_group_layouts,
-    texture_bind_groups,
+    build_accumulation_texture, build_blue_noise_texture, build_compute_pipeline,
+    build_object_id_texture, build_render_pipeline, build_texture, texture_bind_group_layouts,
+    texture_bind_groups, window_icon, TileSize, Vec3, ViewRect,
 };
 use wgpu::{
-    BindGroup, BindGroupLayout, Color, CommandEncoderDescriptor, ComputePassDescriptor,
-    ComputePipeline, Device, DeviceDescriptor, Instance, InstanceDescriptor, Operations, Queue,
-    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RequestAdapterOptions,
-    Sampler, SamplerDescriptor, Surface, SurfaceConfiguration, SurfaceError, Texture,
-    TextureFormat, TextureUsages, TextureViewDescriptor,
+    Adapter, BindGroup, BindGroupLayout, Buffer, BufferDescriptor, BufferUsages, Color,
+    CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline, Device, DeviceDescriptor,
+    Extent3d, ImageCopyBuffer, ImageCopyTexture, ImageDataLayout, Instance, InstanceDescriptor,
+    Maintain, MapMode, Operations, Origin3d, PresentMode, Queue, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, RequestAdapterOptions, Sampler, SamplerDescriptor,
+    Surface, SurfaceConfiguration, SurfaceError, Texture, TextureAspect, TextureFormat,
+    TextureUsages, TextureViewDescriptor,
 };
 use winit::{
     application::ApplicationHandler,
-    dpi::PhysicalSize,
-    event::WindowEvent,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, KeyEvent, MouseButton, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{KeyCode, PhysicalKey},
     window::{Window, WindowAttributes, WindowId},
 };
 
-use crate::util::build_debug_texture;
-
+mod benchmark;
+mod bvh;
 mod camera;
+mod cli;
+mod config;
+mod diagnostics;
+mod display;
 mod geometry;
+mod input;
+mod lut;
+mod material;
+mod overlay;
+mod ray;
+mod scene;
+mod schedule;
+mod settings;
 mod util;
 
+const DEFAULT_TITLE: &str = "Ray Tracing WebGPU";
+const NO_OBJECT: u32 = u32::MAX;
+const DEFAULT_LOCKED_ASPECT: f32 = 16.0 / 9.0;
+
 static DEBUG_TEXTURE: OnceLock<Texture> = OnceLock::new();
+#[cfg_attr(not(feature = "debug-texture"), allow(unused_variables))]
 fn init_debug_texture(device: &Device, queue: &Queue) {
     #[cfg(feature = "debug-texture")]
-    let _ = DEBUG_TEXTURE.set(build_debug_texture(device, queue));
+    let _ = DEBUG_TEXTURE.set(util::build_debug_texture(device, queue));
+}
+
+/// The part of the window the image is rendered to: letterboxed when the image has a fixed
+/// internal resolution, the projection needs a fixed aspect ratio or the user locked one, the
+/// whole window otherwise.
+fn image_rect(
+    window_size: PhysicalSize<u32>,
+    internal_res: Option<PhysicalSize<u32>>,
+    projection: ProjectionMode,
+    locked_aspect: Option<f32>,
+) -> ViewRect {
+    let internal_aspect = internal_res.map(|res| res.width as f32 / res.height as f32);
+    match internal_aspect
+        .or(projection.required_aspect())
+        .or(locked_aspect)
+    {
+        Some(aspect) => ViewRect::letterbox(window_size, aspect),
+        None => ViewRect::full(window_size),
+    }
+}
+
+/// With `args.software`, only CPU adapters (lavapipe, llvmpipe, WARP) are considered. These run every
+/// dispatch on the CPU and are orders of magnitude slower than a GPU; they also tend to have
+/// smaller limits, so large `--tile` sizes may be rejected.
+fn request_adapter(instance: &Instance, surface: Option<&Surface>, args: &Args) -> Option<Adapter> {
+    let adapter = pollster::block_on(instance.request_adapter(&RequestAdapterOptions {
+        power_preference: args.power_preference,
+        compatible_surface: surface,
+        force_fallback_adapter: args.software,
+    }));
+    if adapter.is_none() {
+        eprintln!(
+            "no suitable {}adapter found",
+            if args.software { "software " } else { "GPU " }
+        );
+    }
+    adapter
+}
+
+/// `Fifo` is always supported; without vsync, `Immediate` tears but has the least latency, so it
+/// is preferred over `Mailbox`.
+fn present_mode(supported: &[PresentMode], vsync: Option<bool>) -> PresentMode {
+    match vsync {
+        None => supported[0],
+        Some(true) => PresentMode::Fifo,
+        Some(false) => [PresentMode::Immediate, PresentMode::Mailbox]
+            .into_iter()
+            .find(|mode| supported.contains(mode))
+            .unwrap_or_else(|| {
+                eprintln!(
+                    "The surface can't present without vsync, using {:?}",
+                    supported[0]
+                );
+                supported[0]
+            }),
+    }
+}
+
+/// The window title while the tile size sweep measures `tile_size`.
+fn sweep_title(name: &str, tile_size: TileSize) -> String {
+    format!("{} - benchmarking {} tiles", name, tile_size)
 }
 
 struct WebGPUResources<'a> {
     surface: Surface<'a>,
     surface_config: SurfaceConfiguration,
+    /// The surface was configured for `--direct-present`: it is `Rgba8Unorm`, the format of the
+    /// compute texture, and accepts copies.
+    direct_present: bool,
     device: Device,
     queue: Queue,
 }
 
 impl<'a> WebGPUResources<'a> {
-    fn new(window: Arc<Window>) -> Self {
+    fn new(window: Arc<Window>, args: &Args) -> Self {
         let instance = Instance::new(InstanceDescriptor::default());
         let surface = instance.create_surface(window.clone()).unwrap();
-        let adapter = pollster::block_on(instance.request_adapter(&RequestAdapterOptions {
-            compatible_surface: Some(&surface),
-            ..Default::default()
-        }))
-        .unwrap();
+        let Some(adapter) = request_adapter(&instance, Some(&surface), args) else {
+            std::process::exit(1);
+        };
 
         let (device, queue) =
             pollster::block_on(adapter.request_device(&DeviceDescriptor::default(), None)).unwrap();
 
         let surface_capabilities = surface.get_capabilities(&adapter);
-        let surface_format = surface_capabilities.formats[0];
+        let direct_present = args.direct_present
+            && surface_capabilities
+                .formats
+                .contains(&TextureFormat::Rgba8Unorm)
+            && surface_capabilities
+                .usages
+                .contains(TextureUsages::COPY_DST);
+        if args.direct_present && !direct_present {
+            eprintln!(
+                "The surface can't be an Rgba8Unorm copy destination, \
+                 presenting through the render pass"
+            );
+        }
+        let (surface_format, usage) = if direct_present {
+            (
+                TextureFormat::Rgba8Unorm,
+                TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_DST,
+            )
+        } else {
+            (
+                surface_capabilities.formats[0],
+                TextureUsages::RENDER_ATTACHMENT,
+            )
+        };
 
         let size = window.inner_size();
 
         let surface_config = SurfaceConfiguration {
-            usage: TextureUsages::RENDER_ATTACHMENT,
+            usage,
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: surface_capabilities.present_modes[0],
+            present_mode: present_mode(&surface_capabilities.present_modes, args.vsync),
             desired_maximum_frame_latency: 2,
             alpha_mode: surface_capabilities.alpha_modes[0],
             view_formats: vec![],
@@ -76,6 +192,7 @@ impl<'a> WebGPUResources<'a> {
         Self {
             surface,
             surface_config,
+            direct_present,
             device,
             queue,
         }
@@ -88,105 +205,441 @@ impl<'a> WebGPUResources<'a> {
     }
 }
 
-struct Scene {
-    camera: Camera,
-    objects: Vec<Box<dyn Geometry>>,
-}
-
 struct App<'a> {
     window: Arc<Window>,
+    /// The title given at startup, which `title` decorates while a tile size sweep runs.
+    name: String,
+    title: String,
     size: PhysicalSize<u32>,
+    aspect: f32,
+    aspect_locked: bool,
+    view_rect: ViewRect,
+    internal_res: Option<PhysicalSize<u32>>,
     webgpu_resources: WebGPUResources<'a>,
 
+    tile_size: TileSize,
+    tile_sweep: Option<TileSweep>,
+    tile_schedule: TileSchedule,
+    last_frame_time: Option<Duration>,
+    frame_stats: FrameStats,
+    last_redraw: Option<Instant>,
+    /// When the frame rate in the title was last refreshed, and the frames drawn since.
+    title_refreshed: (Instant, u32),
+    /// Minimum time between redraws from `--fps-cap`.
+    frame_interval: Option<Duration>,
     compute_pipeline: ComputePipeline,
     render_pipeline: RenderPipeline,
 
     sampler: Sampler,
+    object_id_texture: Texture,
+    blue_noise_texture: Texture,
+    pick_buffer: Buffer,
+    cursor_position: PhysicalPosition<f64>,
     texture_bind_group_layouts: [BindGroupLayout; 2],
-    compute_texture_bind_group: BindGroup,
-    render_texture_bind_group: BindGroup,
+    compute_texture: Texture,
+    /// Running average of the frames, read from one and written to the other in turns.
+    accumulation_textures: [Texture; 2],
+    compute_texture_bind_groups: [BindGroup; 2],
+    render_texture_bind_groups: [BindGroup; 2],
     camera_bind_group: BindGroup,
+    settings: RenderSettings,
+    settings_buffer: Buffer,
+    settings_bind_group: BindGroup,
+    color_grading: ColorGrading,
+    color_grading_bind_group: BindGroup,
+    display: DisplaySettings,
+    display_bind_group: BindGroup,
+    help: TextOverlay,
 
     scene: Scene,
 }
 
 impl<'a> App<'a> {
-    fn new(window: Window) -> Self {
+    fn new(window: Window, title: String, args: &Args) -> Self {
         let window = Arc::new(window);
         let size = window.inner_size();
-
-        let webgpu_resources = WebGPUResources::new(window.clone());
+        let aspect = args.aspect.unwrap_or(DEFAULT_LOCKED_ASPECT);
+        let aspect_locked = args.aspect.is_some();
+        let projection = args.projection.unwrap_or_default();
+
+        let webgpu_resources = WebGPUResources::new(window.clone(), args);
+        let limits = webgpu_resources.device.limits();
+
+        let internal_res = args.internal_res.filter(|res| {
+            let fits = res.width.max(res.height) <= limits.max_texture_dimension_2d;
+            if !fits {
+                eprintln!(
+                    "Internal resolution {}x{} exceeds the device limits, using the window size",
+                    res.width, res.height
+                );
+            }
+            fits
+        });
+        let view_rect = image_rect(
+            size,
+            internal_res,
+            projection,
+            aspect_locked.then_some(aspect),
+        );
+        let image_size = internal_res.unwrap_or(view_rect.size());
 
         init_debug_texture(&webgpu_resources.device, &webgpu_resources.queue);
         let sampler = webgpu_resources
             .device
             .create_sampler(&SamplerDescriptor::default());
-        let compute_texture = build_texture(&webgpu_resources.device, size);
+        let compute_texture = build_texture(&webgpu_resources.device, image_size);
+        let object_id_texture = build_object_id_texture(&webgpu_resources.device, image_size);
+        let accumulation_textures =
+            [(); 2].map(|_| build_accumulation_texture(&webgpu_resources.device, image_size));
+        let blue_noise_texture =
+            build_blue_noise_texture(&webgpu_resources.device, &webgpu_resources.queue);
+        let pick_buffer = webgpu_resources.device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: std::mem::size_of::<u32>() as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
 
         let texture_bind_group_layouts = texture_bind_group_layouts(&webgpu_resources.device);
-        let [compute_texture_bind_group, render_texture_bind_group] = texture_bind_groups(
+
+        let mut camera = Camera::new(image_size, &webgpu_resources.device);
+        camera.set_projection(projection, &webgpu_resources.queue);
+        let camera_bind_group = camera.bind_group(&webgpu_resources.device);
+
+        let settings = RenderSettings {
+            near: args.near.unwrap_or(RenderSettings::DEFAULT_NEAR),
+            samples_per_pixel: args
+                .samples
+                .unwrap_or(RenderSettings::DEFAULT_SAMPLES_PER_PIXEL),
+            max_depth: args.max_depth.unwrap_or(RenderSettings::DEFAULT_MAX_DEPTH),
+            tone_map: args.tone_map.unwrap_or_default(),
+            ..Default::default()
+        };
+        let settings_buffer = settings.create_buffer(&webgpu_resources.device);
+        let settings_bind_group =
+            RenderSettings::bind_group(&webgpu_resources.device, &settings_buffer);
+
+        let tile_sizes: Vec<_> = args
+            .tile_sizes
+            .iter()
+            .copied()
+            .filter(|tile| {
+                let fits = tile.fits(&limits);
+                if !fits {
+                    eprintln!("Tile size {} exceeds the device limits, skipping", tile);
+                }
+                fits
+            })
+            .collect();
+        let tile_sweep = (tile_sizes.len() > 1)
+            .then(|| TileSweep::new(tile_sizes.clone()))
+            .flatten();
+        let tile_size = match &tile_sweep {
+            Some(sweep) => sweep.tile_size(),
+            None => tile_sizes.first().copied().unwrap_or_default(),
+        };
+
+        let compute_pipeline = build_compute_pipeline(
+            &webgpu_resources.device,
+            tile_size,
+            &texture_bind_group_layouts[0],
+            &Camera::bind_group_layout(&webgpu_resources.device),
+            &RenderSettings::bind_group_layout(&webgpu_resources.device),
+            &Scene::bind_group_layout(&webgpu_resources.device),
+        );
+        let tile_schedule = TileSchedule::new(
+            &webgpu_resources.device,
+            args.tile_order.unwrap_or_default(),
+            tile_size,
+            image_size,
+        );
+        let (compute_texture_bind_groups, render_texture_bind_groups) = texture_bind_groups(
             &webgpu_resources.device,
             &compute_texture,
+            &object_id_texture,
+            &blue_noise_texture,
+            &accumulation_textures,
+            tile_schedule.buffer(),
             &texture_bind_group_layouts,
             &sampler,
             DEBUG_TEXTURE.get(),
         );
 
-        let camera = Camera::new(size, &webgpu_resources.device);
-        let camera_bind_group = camera.bind_group(&webgpu_resources.device);
+        let lut = args.lut.as_ref().and_then(|path| {
+            Lut::load(path)
+                .map_err(|e| eprintln!("Failed to load LUT {}", e))
+                .ok()
+        });
+        let color_grading =
+            ColorGrading::new(&webgpu_resources.device, &webgpu_resources.queue, lut);
+        let color_grading_bind_group = color_grading.bind_group(&webgpu_resources.device);
 
-        let compute_pipeline = build_compute_pipeline(
+        let display = DisplaySettings::new(
             &webgpu_resources.device,
-            &texture_bind_group_layouts[0],
-            &Camera::bind_group_layout(&webgpu_resources.device),
+            webgpu_resources.surface_config.format,
         );
+        let display_bind_group = display.bind_group(&webgpu_resources.device, &blue_noise_texture);
+
         let render_pipeline = build_render_pipeline(
             &webgpu_resources.device,
             &texture_bind_group_layouts[1],
+            &ColorGrading::bind_group_layout(&webgpu_resources.device),
+            &DisplaySettings::bind_group_layout(&webgpu_resources.device),
+            webgpu_resources.surface_config.format,
+        );
+
+        let help = TextOverlay::new(
+            &webgpu_resources.device,
             webgpu_resources.surface_config.format,
         );
 
         println!("{:?}", camera);
 
+        let mut scene = Scene::new(&webgpu_resources.device, &webgpu_resources.queue, camera);
+        let ground = scene.add_material(&Lambertian::new(Vec3(0.8, 0.8, 0.0)));
+        let center = scene.add_material(&Lambertian::new(Vec3(0.1, 0.2, 0.5)));
+        let left = scene.add_material(&Dielectric::new(1.5));
+        let right = scene.add_material(&Metal::new(Vec3(0.8, 0.6, 0.2), 1.0));
+        scene.add_with_material(Box::new(Sphere::new(Vec3(0.0, 0.0, -1.0), 0.5)), center);
+        scene.add_with_material(Box::new(Sphere::new(Vec3(-1.0, 0.0, -1.0), 0.5)), left);
+        scene.add_with_material(Box::new(Sphere::new(Vec3(1.0, 0.0, -1.0), 0.5)), right);
+        scene.add_with_material(
+            Box::new(Sphere::new(Vec3(0.0, -100.5, -1.0), 100.0)),
+            ground,
+        );
+        if let Some(path) = &args.environment {
+            if let Err(e) = scene.set_environment(path) {
+                eprintln!("Failed to load environment map {}: {}", path, e);
+            }
+        }
+
+        let name = title.clone();
+        let title = match &tile_sweep {
+            Some(sweep) => sweep_title(&name, sweep.tile_size()),
+            None => title,
+        };
+
         Self {
             window,
+            name,
+            title,
             size,
+            aspect,
+            aspect_locked,
+            view_rect,
+            internal_res,
             webgpu_resources,
+            tile_size,
+            tile_sweep,
+            tile_schedule,
+            last_frame_time: None,
+            frame_stats: FrameStats::new(),
+            last_redraw: None,
+            title_refreshed: (Instant::now(), 0),
+            frame_interval: (args.fps_cap > 0)
+                .then(|| Duration::from_secs_f64(1.0 / args.fps_cap as f64)),
             compute_pipeline,
             render_pipeline,
             sampler,
+            object_id_texture,
+            blue_noise_texture,
+            pick_buffer,
+            cursor_position: PhysicalPosition::default(),
             texture_bind_group_layouts,
-            compute_texture_bind_group,
-            render_texture_bind_group,
+            compute_texture,
+            accumulation_textures,
+            compute_texture_bind_groups,
+            render_texture_bind_groups,
             camera_bind_group,
-            scene: Scene {
-                camera,
-                objects: vec![],
-            },
+            settings,
+            settings_buffer,
+            settings_bind_group,
+            color_grading,
+            color_grading_bind_group,
+            display,
+            display_bind_group,
+            help,
+            scene,
         }
     }
 
     fn resize(&mut self, new_size: PhysicalSize<u32>) {
         self.size = new_size;
         self.webgpu_resources.resize_surface(new_size);
+        self.resize_image();
+    }
+
+    fn resize_image(&mut self) {
+        self.view_rect = image_rect(
+            self.size,
+            self.internal_res,
+            self.scene.camera.projection(),
+            self.aspect_locked.then_some(self.aspect),
+        );
+        let image_size = self.image_size();
+
+        self.compute_texture = build_texture(&self.webgpu_resources.device, image_size);
+        self.object_id_texture = build_object_id_texture(&self.webgpu_resources.device, image_size);
+        self.accumulation_textures =
+            [(); 2].map(|_| build_accumulation_texture(&self.webgpu_resources.device, image_size));
+        self.settings.reset_accumulation();
+        self.set_tile_order(self.tile_schedule.order());
+
+        self.scene
+            .camera
+            .resize_viewport(&self.webgpu_resources.queue, image_size);
+        self.refresh_help();
+    }
+
+    /// Size of the rendered image, which `view_rect` scales to the window.
+    fn image_size(&self) -> PhysicalSize<u32> {
+        self.internal_res.unwrap_or(self.view_rect.size())
+    }
+
+    fn toggle_aspect_lock(&mut self) {
+        self.aspect_locked = !self.aspect_locked;
+        self.resize_image();
+    }
+
+    fn toggle_projection(&mut self) {
+        let projection = match self.scene.camera.projection() {
+            ProjectionMode::Equirectangular => ProjectionMode::Perspective,
+            ProjectionMode::Perspective | ProjectionMode::Orthographic { .. } => {
+                ProjectionMode::Equirectangular
+            }
+        };
+        self.scene
+            .camera
+            .set_projection(projection, &self.webgpu_resources.queue);
+        self.resize_image();
+    }
+
+    fn update(&mut self) {
+        if self
+            .scene
+            .sync_to_gpu(&self.webgpu_resources.device, &self.webgpu_resources.queue)
+        {
+            self.settings.reset_accumulation();
+        }
+        self.settings.advance_frame();
+        self.settings
+            .upload(&self.webgpu_resources.queue, &self.settings_buffer);
+        self.display
+            .set_tone_map(&self.webgpu_resources.queue, self.settings.tone_map);
+        self.display
+            .set_dither(&self.webgpu_resources.queue, self.settings.dither);
+    }
+
+    fn set_title(&mut self, title: impl Into<String>) {
+        self.title = title.into();
+        self.window.set_title(&self.title);
+    }
+
+    /// Shows the frame rate and the number of accumulated frames after the title, refreshed
+    /// about once a second.
+    fn refresh_title(&mut self, now: Instant) {
+        let (since, frames) = &mut self.title_refreshed;
+        *frames += 1;
+        let elapsed = now - *since;
+        if elapsed < Duration::from_secs(1) {
+            return;
+        }
+        let fps = *frames as f64 / elapsed.as_secs_f64();
+        self.title_refreshed = (now, 0);
+        self.window.set_title(&format!(
+            "{} - {:.0} fps, {} frames accumulated",
+            self.title, fps, self.settings.frame_count
+        ));
+    }
 
-        let compute_texture = build_texture(&self.webgpu_resources.device, self.size);
-        let [compute_texture_bind_group, render_texture_bind_group] = texture_bind_groups(
+    fn set_tile_size(&mut self, tile_size: TileSize) {
+        self.tile_size = tile_size;
+        self.compute_pipeline = build_compute_pipeline(
             &self.webgpu_resources.device,
-            &compute_texture,
+            tile_size,
+            &self.texture_bind_group_layouts[0],
+            &Camera::bind_group_layout(&self.webgpu_resources.device),
+            &RenderSettings::bind_group_layout(&self.webgpu_resources.device),
+            &Scene::bind_group_layout(&self.webgpu_resources.device),
+        );
+        self.set_tile_order(self.tile_schedule.order());
+    }
+
+    /// Rebuilds the tile schedule, which depends on the tile size and the image size, and with it
+    /// the image bind groups.
+    fn set_tile_order(&mut self, order: TileOrder) {
+        self.tile_schedule = TileSchedule::new(
+            &self.webgpu_resources.device,
+            order,
+            self.tile_size,
+            self.image_size(),
+        );
+        (
+            self.compute_texture_bind_groups,
+            self.render_texture_bind_groups,
+        ) = texture_bind_groups(
+            &self.webgpu_resources.device,
+            &self.compute_texture,
+            &self.object_id_texture,
+            &self.blue_noise_texture,
+            &self.accumulation_textures,
+            self.tile_schedule.buffer(),
             &self.texture_bind_group_layouts,
             &self.sampler,
             DEBUG_TEXTURE.get(),
         );
-        self.compute_texture_bind_group = compute_texture_bind_group;
-        self.render_texture_bind_group = render_texture_bind_group;
+    }
 
-        self.scene
-            .camera
-            .resize_viewport(&self.webgpu_resources.queue, self.size);
+    /// Reads back the object index the last frame wrote under `position`, copying only that
+    /// single texel of the object-ID texture.
+    fn pick(&self, position: PhysicalPosition<f64>) -> Option<u32> {
+        let (x, y) = self.view_rect.local_pixel(position.x, position.y)?;
+        let image_size = self.image_size();
+        let x = x * image_size.width / self.view_rect.width;
+        let y = y * image_size.height / self.view_rect.height;
+
+        let device = &self.webgpu_resources.device;
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                texture: &self.object_id_texture,
+                mip_level: 0,
+                origin: Origin3d { x, y, z: 0 },
+                aspect: TextureAspect::All,
+            },
+            ImageCopyBuffer {
+                buffer: &self.pick_buffer,
+                layout: ImageDataLayout::default(),
+            },
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.webgpu_resources.queue.submit([encoder.finish()]);
+
+        let slice = self.pick_buffer.slice(..);
+        slice.map_async(MapMode::Read, |_| {});
+        device.poll(Maintain::Wait);
+        let object_id = bytemuck::cast_slice::<u8, u32>(&slice.get_mapped_range())[0];
+        self.pick_buffer.unmap();
+
+        (object_id != NO_OBJECT).then_some(object_id)
     }
 
-    fn update(&mut self) {}
+    /// Whether this frame can skip the render pass and copy the compute texture straight to the
+    /// surface: the surface was configured for it, the image fills the window 1:1 and nothing is
+    /// applied on top of it (tone mapping, gamma encoding, colour grading, the help overlay, the
+    /// debug texture).
+    fn presents_directly(&self) -> bool {
+        self.webgpu_resources.direct_present
+            && self.image_size() == self.size
+            && !self.display.transforms()
+            && !self.color_grading.enabled()
+            && !self.help.visible
+            && DEBUG_TEXTURE.get().is_none()
+    }
 
     fn render(&mut self) -> Result<(), SurfaceError> {
         let output = self.webgpu_resources.surface.get_current_texture()?;
@@ -199,15 +652,25 @@ impl<'a> App<'a> {
             .device
             .create_command_encoder(&CommandEncoderDescriptor::default());
 
+        let read = (self.settings.frame_count % 2) as usize;
         {
             let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
             compute_pass.set_pipeline(&self.compute_pipeline);
-            compute_pass.set_bind_group(0, &self.compute_texture_bind_group, &[]);
+            compute_pass.set_bind_group(0, &self.compute_texture_bind_groups[read], &[]);
             compute_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-            compute_pass.dispatch_workgroups(self.size.width, self.size.height, 1);
+            compute_pass.set_bind_group(2, &self.settings_bind_group, &[]);
+            compute_pass.set_bind_group(3, self.scene.bind_group(), &[]);
+            let (workgroups_x, workgroups_y) = self.tile_schedule.workgroups();
+            compute_pass.dispatch_workgroups(workgroups_x, workgroups_y, 1);
         }
 
-        {
+        if self.presents_directly() {
+            encoder.copy_texture_to_texture(
+                self.compute_texture.as_image_copy(),
+                output.texture.as_image_copy(),
+                self.compute_texture.size(),
+            );
+        } else {
             let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
                 color_attachments: &[Some(RenderPassColorAttachment {
                     view: &view,
@@ -219,20 +682,140 @@ impl<'a> App<'a> {
                 })],
                 ..Default::default()
             });
+            render_pass.set_viewport(
+                self.view_rect.x as f32,
+                self.view_rect.y as f32,
+                self.view_rect.width as f32,
+                self.view_rect.height as f32,
+                0.0,
+                1.0,
+            );
             render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.render_texture_bind_group, &[]);
+            render_pass.set_bind_group(0, &self.render_texture_bind_groups[read], &[]);
+            render_pass.set_bind_group(1, &self.color_grading_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.display_bind_group, &[]);
             render_pass.draw(0..6, 0..1);
+
+            self.help.draw(&mut render_pass, self.size);
         }
 
+        let submitted = Instant::now();
         self.webgpu_resources.queue.submit([encoder.finish()]);
+        if self.tile_sweep.is_some() {
+            self.webgpu_resources.device.poll(Maintain::Wait);
+            self.last_frame_time = Some(submitted.elapsed());
+        }
         output.present();
 
         Ok(())
     }
 
+    fn key_pressed(&mut self, key: KeyCode) {
+        let Some(action) = input::action_for(key) else {
+            return;
+        };
+        match action {
+            Action::ToggleHelp => self.help.visible = !self.help.visible,
+            Action::ToggleAspectLock => self.toggle_aspect_lock(),
+            Action::ToggleProjection => self.toggle_projection(),
+            Action::ToggleColorGrading => {
+                let enabled = !self.color_grading.enabled();
+                self.color_grading
+                    .set_enabled(&self.webgpu_resources.queue, enabled);
+            }
+            Action::ToggleGamma => {
+                let gamma = !self.display.gamma();
+                self.display.set_gamma(&self.webgpu_resources.queue, gamma);
+            }
+            Action::CycleToneMap => {
+                self.settings.tone_map = match self.settings.tone_map {
+                    ToneMap::None => ToneMap::Reinhard,
+                    ToneMap::Reinhard => ToneMap::Aces,
+                    ToneMap::Aces => ToneMap::None,
+                }
+            }
+            Action::ToggleDither => self.settings.dither = !self.settings.dither,
+            Action::ToggleTileOrder => self.set_tile_order(match self.tile_schedule.order() {
+                TileOrder::Linear => TileOrder::Morton,
+                TileOrder::Morton => TileOrder::Linear,
+            }),
+            Action::PrintFrameStats => print!("{}", self.frame_stats.report()),
+        }
+        self.refresh_help();
+    }
+
+    fn action_state(&self, action: Action) -> Option<String> {
+        let on_off = |on: bool| if on { "on" } else { "off" }.to_owned();
+        match action {
+            Action::ToggleHelp | Action::PrintFrameStats => None,
+            Action::ToggleAspectLock => Some(if self.aspect_locked {
+                format!("on ({:.2}:1)", self.aspect)
+            } else {
+                on_off(false)
+            }),
+            Action::ToggleProjection => Some(
+                match self.scene.camera.projection() {
+                    ProjectionMode::Perspective => "perspective",
+                    ProjectionMode::Equirectangular => "equirectangular",
+                    ProjectionMode::Orthographic { .. } => "orthographic",
+                }
+                .to_owned(),
+            ),
+            Action::ToggleGamma => Some(on_off(self.display.gamma())),
+            Action::CycleToneMap => Some(self.settings.tone_map.to_string()),
+            Action::ToggleDither => Some(on_off(self.settings.dither)),
+            Action::ToggleTileOrder => Some(self.tile_schedule.order().to_string()),
+            Action::ToggleColorGrading => Some(if self.color_grading.loaded() {
+                on_off(self.color_grading.enabled())
+            } else {
+                "no LUT loaded".to_owned()
+            }),
+        }
+    }
+
+    fn refresh_help(&mut self) {
+        if !self.help.visible {
+            return;
+        }
+
+        let mut lines = vec!["Key bindings".to_owned()];
+        for binding in KEY_BINDINGS {
+            let mut line = format!("  {:<6} {:<24}", binding.label, binding.description);
+            if let Some(state) = self.action_state(binding.action) {
+                line += &state;
+            }
+            lines.push(line);
+        }
+        lines.push(format!("  {:<6} {:<24}", "Click", "select object"));
+        lines.push(String::new());
+        let image_size = self.image_size();
+        lines.push(format!(
+            "image {}x{}, tile {}",
+            image_size.width, image_size.height, self.tile_size
+        ));
+
+        self.help.set_text(
+            &self.webgpu_resources.device,
+            &self.webgpu_resources.queue,
+            &lines,
+        );
+    }
+
     fn window_event(&mut self, event_loop: &ActiveEventLoop, event: WindowEvent) {
         match event {
             WindowEvent::RedrawRequested => {
+                if let (Some(interval), Some(last_redraw)) = (self.frame_interval, self.last_redraw)
+                {
+                    if let Some(wait) = interval.checked_sub(last_redraw.elapsed()) {
+                        std::thread::sleep(wait);
+                    }
+                }
+                let now = Instant::now();
+                if let Some(last_redraw) = self.last_redraw.replace(now) {
+                    self.frame_stats.record(now - last_redraw);
+                }
+                self.refresh_title(now);
+
                 self.update();
                 match self.render() {
                     Ok(_) => {}
@@ -240,9 +823,44 @@ impl<'a> App<'a> {
                     Err(SurfaceError::OutOfMemory) => event_loop.exit(),
                     Err(e) => eprintln!("{:?}", e),
                 }
+                if let (Some(sweep), Some(frame_time)) =
+                    (self.tile_sweep.as_mut(), self.last_frame_time.take())
+                {
+                    match sweep.record(frame_time) {
+                        SweepStep::Continue => {}
+                        SweepStep::Switch(tile_size) => {
+                            self.set_tile_size(tile_size);
+                            self.set_title(sweep_title(&self.name, tile_size));
+                        }
+                        SweepStep::Done => {
+                            print!("{}", sweep.report());
+                            self.set_title(self.name.clone());
+                            event_loop.exit();
+                        }
+                    }
+                }
                 self.window.request_redraw();
             }
             WindowEvent::Resized(new_size) => self.resize(new_size),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => self.key_pressed(key),
+            WindowEvent::CursorMoved { position, .. } => self.cursor_position = position,
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => match self.pick(self.cursor_position) {
+                Some(object_id) => println!("Selected object {}", object_id),
+                None => println!("No object selected"),
+            },
             _ => (),
         }
     }
@@ -250,18 +868,23 @@ impl<'a> App<'a> {
 
 #[derive(Default)]
 struct AppHolder<'a> {
-    title: &'static str,
+    args: Args,
     app: Option<App<'a>>,
 }
 
 impl<'a> ApplicationHandler for AppHolder<'a> {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let window = event_loop
-            .create_window(WindowAttributes::default().with_title(self.title))
-            .unwrap();
+        let title = self.args.title.as_deref().unwrap_or(DEFAULT_TITLE);
+        let mut attributes = WindowAttributes::default()
+            .with_title(title)
+            .with_window_icon(window_icon());
+        if let Some(size) = self.args.window_size {
+            attributes = attributes.with_inner_size(size);
+        }
+        let window = event_loop.create_window(attributes).unwrap();
         window.request_redraw();
 
-        self.app = Some(App::new(window));
+        self.app = Some(App::new(window, title.to_owned(), &self.args));
     }
 
     fn window_event(
@@ -278,6 +901,7 @@ impl<'a> ApplicationHandler for AppHolder<'a> {
 
         match event {
             WindowEvent::CloseRequested => {
+                print!("{}", app.frame_stats.report());
                 self.app = None;
                 event_loop.exit();
             }
@@ -289,8 +913,28 @@ impl<'a> ApplicationHandler for AppHolder<'a> {
 
 fn main() {
     env_logger::init();
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, Args::usage());
+            std::process::exit(2);
+        }
+    };
+    if args.dump_layout {
+        print!("{}", diagnostics::layout_report());
+        return;
+    }
+    if args.info {
+        let instance = Instance::new(InstanceDescriptor::default());
+        let Some(adapter) = request_adapter(&instance, None, &args) else {
+            std::process::exit(1);
+        };
+        print!("{}", diagnostics::adapter_report(&adapter));
+        return;
+    }
+
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Wait);
-    let mut app = AppHolder::default();
+    let mut app = AppHolder { args, app: None };
     event_loop.run_app(&mut app).unwrap();
 }
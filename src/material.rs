@@ -0,0 +1,333 @@
+use bytemuck::{Pod, Zeroable};
+use rand::Rng;
+
+use crate::{geometry::HitRecord, ray::Ray, util::Vec3};
+
+/// `Material` in `compute.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct GpuMaterial {
+    pub albedo: [f32; 3],
+    pub kind: u32,
+    pub emit: [f32; 3],
+    pub fuzz: f32,
+    pub ior: f32,
+    pub _pad: [u32; 3],
+}
+
+impl GpuMaterial {
+    pub const LAMBERTIAN: u32 = 0;
+    pub const METAL: u32 = 1;
+    pub const DIELECTRIC: u32 = 2;
+    pub const DIFFUSE_LIGHT: u32 = 3;
+
+    /// Scatters like the material this was made from, choosing by `kind` the way `scatter` in
+    /// `compute.wgsl` does; unknown kinds are diffuse there too.
+    pub fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut impl Rng) -> Option<(Vec3, Ray)> {
+        let albedo = Vec3(self.albedo[0], self.albedo[1], self.albedo[2]);
+        match self.kind {
+            Self::DIFFUSE_LIGHT => DiffuseLight::new(self.emitted()).scatter(ray, hit, rng),
+            Self::DIELECTRIC => Dielectric::new(self.ior).scatter(ray, hit, rng),
+            Self::METAL => Metal::new(albedo, self.fuzz).scatter(ray, hit, rng),
+            _ => Lambertian::new(albedo).scatter(ray, hit, rng),
+        }
+    }
+
+    pub fn emitted(&self) -> Vec3 {
+        Vec3(self.emit[0], self.emit[1], self.emit[2])
+    }
+}
+
+pub trait Material {
+    /// The attenuation and the scattered ray, or `None` if the ray is absorbed.
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut impl Rng) -> Option<(Vec3, Ray)>;
+
+    /// Light given off by the surface itself, added to a path before it scatters.
+    #[allow(dead_code)]
+    fn emitted(&self) -> Vec3 {
+        Vec3::origin()
+    }
+
+    fn to_gpu(&self) -> GpuMaterial;
+}
+
+/// Ideal diffuse surface. Offsetting the normal by a random unit vector gives directions with a
+/// cosine distribution around the normal.
+#[derive(Clone, Copy, Debug)]
+pub struct Lambertian {
+    pub albedo: Vec3,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Vec3) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for Lambertian {
+    fn scatter(&self, _ray: &Ray, hit: &HitRecord, rng: &mut impl Rng) -> Option<(Vec3, Ray)> {
+        let mut direction = hit.normal + Vec3::random_unit_vector(rng);
+        // The random vector can all but cancel the normal out.
+        if direction.near_zero() {
+            direction = hit.normal;
+        }
+        Some((self.albedo, Ray::new(hit.point, direction)))
+    }
+
+    fn to_gpu(&self) -> GpuMaterial {
+        GpuMaterial {
+            albedo: self.albedo.as_array(),
+            kind: GpuMaterial::LAMBERTIAN,
+            ..Default::default()
+        }
+    }
+}
+
+/// Mirror-like surface. `fuzz` jitters the reflected direction by up to that fraction of its
+/// length, from a perfect mirror at 0 to a brushed look at 1.
+#[derive(Clone, Copy, Debug)]
+pub struct Metal {
+    pub albedo: Vec3,
+    pub fuzz: f32,
+}
+
+impl Metal {
+    pub fn new(albedo: Vec3, fuzz: f32) -> Self {
+        Self {
+            albedo,
+            fuzz: fuzz.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Material for Metal {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut impl Rng) -> Option<(Vec3, Ray)> {
+        let reflected = ray.direction.normalize().reflect(hit.normal)
+            + Vec3::random_in_unit_sphere(rng) * self.fuzz;
+        // Fuzz can push grazing reflections into the surface; those are absorbed.
+        (reflected.dot(hit.normal) > 0.0).then(|| (self.albedo, Ray::new(hit.point, reflected)))
+    }
+
+    fn to_gpu(&self) -> GpuMaterial {
+        GpuMaterial {
+            albedo: self.albedo.as_array(),
+            kind: GpuMaterial::METAL,
+            fuzz: self.fuzz,
+            ..Default::default()
+        }
+    }
+}
+
+/// Clear refractive material such as glass or water, with `ior` its index of refraction relative
+/// to the surrounding air. Rays are refracted or reflected at random, in proportion to the
+/// reflectance at their angle of incidence, and are never absorbed.
+#[derive(Clone, Copy, Debug)]
+pub struct Dielectric {
+    pub ior: f32,
+}
+
+impl Dielectric {
+    pub fn new(ior: f32) -> Self {
+        Self { ior }
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut impl Rng) -> Option<(Vec3, Ray)> {
+        let ratio = if hit.front_face {
+            1.0 / self.ior
+        } else {
+            self.ior
+        };
+        let direction = ray.direction.normalize();
+        let cos_theta = (-direction).dot(hit.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        let total_internal_reflection = ratio * sin_theta > 1.0;
+        let scattered = if total_internal_reflection || reflectance(cos_theta, ratio) > rng.gen() {
+            direction.reflect(hit.normal)
+        } else {
+            direction.refract(hit.normal, ratio)
+        };
+        Some((Vec3(1.0, 1.0, 1.0), Ray::new(hit.point, scattered)))
+    }
+
+    fn to_gpu(&self) -> GpuMaterial {
+        GpuMaterial {
+            albedo: [1.0; 3],
+            kind: GpuMaterial::DIELECTRIC,
+            ior: self.ior,
+            ..Default::default()
+        }
+    }
+}
+
+/// Area light: emits `emit` from every point of the surface and absorbs whatever hits it.
+/// Components above 1 make it brighter than a white diffuse surface under the sky.
+#[derive(Clone, Copy, Debug)]
+pub struct DiffuseLight {
+    pub emit: Vec3,
+}
+
+impl DiffuseLight {
+    #[allow(dead_code)]
+    pub fn new(emit: Vec3) -> Self {
+        Self { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray: &Ray, _hit: &HitRecord, _rng: &mut impl Rng) -> Option<(Vec3, Ray)> {
+        None
+    }
+
+    fn emitted(&self) -> Vec3 {
+        self.emit
+    }
+
+    fn to_gpu(&self) -> GpuMaterial {
+        GpuMaterial {
+            kind: GpuMaterial::DIFFUSE_LIGHT,
+            emit: self.emit.as_array(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Schlick's approximation of the Fresnel reflectance, between 0 and 1.
+pub fn reflectance(cosine: f32, refraction_ratio: f32) -> f32 {
+    let r0 = ((1.0 - refraction_ratio) / (1.0 + refraction_ratio)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /// A hit at the origin of a floor facing +y, by a ray coming down along `direction`.
+    fn floor_hit(direction: Vec3) -> (Ray, HitRecord) {
+        let ray = Ray::new(-direction, direction);
+        let hit = HitRecord::new(&ray, 1.0, Vec3::j());
+        (ray, hit)
+    }
+
+    #[test]
+    fn lambertian_scatters_into_the_hemisphere() {
+        let mut rng = StdRng::seed_from_u64(516);
+        let albedo = Vec3(0.2, 0.4, 0.6);
+        let (ray, hit) = floor_hit(Vec3(1.0, -1.0, 0.0));
+        for _ in 0..1000 {
+            let (attenuation, scattered) = Lambertian::new(albedo)
+                .scatter(&ray, &hit, &mut rng)
+                .unwrap();
+            assert_eq!(attenuation, albedo);
+            assert_eq!(scattered.origin, hit.point);
+            assert!(scattered.direction.dot(hit.normal) >= 0.0);
+            assert!(!scattered.direction.near_zero());
+        }
+    }
+
+    #[test]
+    fn polished_metal_obeys_the_reflection_law() {
+        let mut rng = StdRng::seed_from_u64(517);
+        let incoming = Vec3(1.0, -2.0, 0.5);
+        let (ray, hit) = floor_hit(incoming);
+        let (attenuation, scattered) = Metal::new(Vec3(0.9, 0.8, 0.7), 0.0)
+            .scatter(&ray, &hit, &mut rng)
+            .unwrap();
+        assert_eq!(attenuation, Vec3(0.9, 0.8, 0.7));
+
+        // Same angle to the normal, mirrored across it, in the plane of incidence.
+        let reflected = scattered.direction;
+        let angle_in = (-incoming).angle_between(&hit.normal);
+        let angle_out = reflected.angle_between(&hit.normal);
+        assert!((angle_in - angle_out).abs() < 1e-6);
+        assert!(incoming.cross(hit.normal).dot(reflected).abs() < 1e-6);
+        assert!((reflected - Vec3(1.0, 2.0, 0.5).normalize()).length() < 1e-6);
+    }
+
+    #[test]
+    fn fuzzy_metal_never_scatters_into_the_surface() {
+        let mut rng = StdRng::seed_from_u64(517);
+        let (ray, hit) = floor_hit(Vec3(1.0, -0.2, 0.0));
+        let metal = Metal::new(Vec3(1.0, 1.0, 1.0), 5.0);
+        assert_eq!(metal.fuzz, 1.0);
+        for _ in 0..1000 {
+            if let Some((_, scattered)) = metal.scatter(&ray, &hit, &mut rng) {
+                assert!(scattered.direction.dot(hit.normal) > 0.0);
+            }
+        }
+    }
+
+    /// Angles of the incoming and refracted rays to the normal line, over the scatters that
+    /// went through the surface.
+    fn refraction_angles(ior: f32, direction: Vec3) -> Vec<(f32, f32)> {
+        let mut rng = StdRng::seed_from_u64(518);
+        let (ray, hit) = floor_hit(direction);
+        let incoming = (-direction).angle_between(&hit.normal);
+        (0..200)
+            .filter_map(|_| Dielectric::new(ior).scatter(&ray, &hit, &mut rng))
+            .map(|(_, scattered)| scattered.direction)
+            .filter(|direction| direction.dot(hit.normal) < 0.0)
+            .map(|direction| (incoming, direction.angle_between(&-hit.normal)))
+            .collect()
+    }
+
+    #[test]
+    fn dielectric_bends_towards_the_normal_on_entry() {
+        let angles = refraction_angles(1.5, Vec3(1.0, -1.0, 0.0));
+        assert!(!angles.is_empty());
+        for (incoming, refracted) in angles {
+            assert!(refracted < incoming);
+            assert!((refracted.sin() - incoming.sin() / 1.5).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn dielectric_bends_away_from_the_normal_on_exit() {
+        // From inside the glass, so the hit is on the back face.
+        let angles = refraction_angles(1.5, Vec3(0.3, 1.0, 0.0));
+        assert!(!angles.is_empty());
+        for (incoming, refracted) in angles {
+            assert!(refracted > incoming);
+            assert!((refracted.sin() - incoming.sin() * 1.5).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn dielectric_reflects_everything_past_the_critical_angle() {
+        assert!(refraction_angles(1.5, Vec3(1.0, 0.5, 0.0)).is_empty());
+    }
+
+    #[test]
+    fn reflectance_stays_between_0_and_1() {
+        for ratio in [1.0 / 2.4, 1.0 / 1.5, 1.0, 1.33, 1.5, 2.4] {
+            for step in 0..=100 {
+                let cosine = step as f32 / 100.0;
+                let r = reflectance(cosine, ratio);
+                assert!((0.0..=1.0).contains(&r), "{} at {}, {}", r, cosine, ratio);
+            }
+            assert_eq!(reflectance(0.0, ratio), 1.0);
+        }
+        assert!((reflectance(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-6);
+    }
+
+    #[test]
+    fn only_lights_emit() {
+        assert_eq!(
+            Lambertian::new(Vec3(1.0, 1.0, 1.0)).emitted(),
+            Vec3::origin()
+        );
+        assert_eq!(
+            Metal::new(Vec3(1.0, 1.0, 1.0), 0.0).emitted(),
+            Vec3::origin()
+        );
+        assert_eq!(Dielectric::new(1.5).emitted(), Vec3::origin());
+        assert_eq!(
+            DiffuseLight::new(Vec3(2.0, 3.0, 4.0)).emitted(),
+            Vec3(2.0, 3.0, 4.0)
+        );
+    }
+}
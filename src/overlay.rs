@@ -0,0 +1,170 @@
+use font8x8::legacy::BASIC_LEGACY;
+use wgpu::{
+    include_wgsl, util::DeviceExt, util::TextureDataOrder, BindGroup, BindGroupDescriptor,
+    BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindingResource, BlendState,
+    ColorTargetState, ColorWrites, Device, Extent3d, MultisampleState, PipelineCompilationOptions,
+    PipelineLayoutDescriptor, PrimitiveState, Queue, RenderPass, RenderPipeline,
+    RenderPipelineDescriptor, Sampler, SamplerDescriptor, TextureDescriptor, TextureFormat,
+    TextureUsages, TextureViewDescriptor,
+};
+use winit::dpi::PhysicalSize;
+
+use crate::util::RENDER_TEXTURE_LAYOUT_ENTRIES;
+
+const GLYPH_SIZE: u32 = 8;
+const LINE_SPACING: u32 = 3;
+const PADDING: u32 = 6;
+const MARGIN: u32 = 16;
+const SCALE: u32 = 2;
+
+const BACKGROUND: [u8; 4] = [0, 0, 0, 176];
+const FOREGROUND: [u8; 4] = [255, 255, 255, 255];
+
+/// A semi-transparent text panel drawn over the top-left corner of the window.
+///
+/// Text is rasterised on the CPU with an 8x8 bitmap font (ASCII only, anything else renders as
+/// `?`) whenever it changes, and drawn as a nearest-sampled, alpha-blended quad at 2x scale.
+pub struct TextOverlay {
+    pipeline: RenderPipeline,
+    layout: BindGroupLayout,
+    sampler: Sampler,
+    panel: Option<(BindGroup, PhysicalSize<u32>)>,
+    pub visible: bool,
+}
+
+impl TextOverlay {
+    pub fn new(device: &Device, target_format: TextureFormat) -> Self {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: RENDER_TEXTURE_LAYOUT_ENTRIES,
+        });
+        let shader = device.create_shader_module(include_wgsl!("overlay.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vert_main",
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "frag_main",
+                compilation_options: PipelineCompilationOptions::default(),
+                targets: &[Some(ColorTargetState {
+                    format: target_format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::all(),
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            layout,
+            sampler: device.create_sampler(&SamplerDescriptor::default()),
+            panel: None,
+            visible: false,
+        }
+    }
+
+    pub fn set_text(&mut self, device: &Device, queue: &Queue, lines: &[String]) {
+        let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0) as u32;
+        let rows = lines.len() as u32;
+        let size = PhysicalSize::new(
+            columns * GLYPH_SIZE + 2 * PADDING,
+            (rows * (GLYPH_SIZE + LINE_SPACING)).saturating_sub(LINE_SPACING) + 2 * PADDING,
+        );
+
+        let mut pixels = BACKGROUND.repeat((size.width * size.height) as usize);
+        for (row, line) in lines.iter().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                let glyph = BASIC_LEGACY[if c.is_ascii() {
+                    c as usize
+                } else {
+                    '?' as usize
+                }];
+                let x0 = PADDING + column as u32 * GLYPH_SIZE;
+                let y0 = PADDING + row as u32 * (GLYPH_SIZE + LINE_SPACING);
+                for (dy, bits) in glyph.iter().enumerate() {
+                    for dx in 0..GLYPH_SIZE {
+                        if bits & (1 << dx) != 0 {
+                            let i = ((y0 + dy as u32) * size.width + x0 + dx) as usize * 4;
+                            pixels[i..i + 4].copy_from_slice(&FOREGROUND);
+                        }
+                    }
+                }
+            }
+        }
+
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                size: Extent3d {
+                    width: size.width,
+                    height: size.height,
+                    depth_or_array_layers: 1,
+                },
+                format: TextureFormat::Rgba8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING,
+                label: None,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                view_formats: &[TextureFormat::Rgba8Unorm],
+            },
+            TextureDataOrder::LayerMajor,
+            &pixels,
+        );
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        self.panel = Some((bind_group, size));
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>, window_size: PhysicalSize<u32>) {
+        let Some((bind_group, size)) = &self.panel else {
+            return;
+        };
+        if !self.visible || window_size.width <= MARGIN || window_size.height <= MARGIN {
+            return;
+        }
+
+        // The viewport has to stay inside the surface, so shrink the panel on small windows.
+        let width = (size.width * SCALE).min(window_size.width - MARGIN);
+        let height = (size.height * SCALE).min(window_size.height - MARGIN);
+        render_pass.set_viewport(
+            MARGIN as f32,
+            MARGIN as f32,
+            width as f32,
+            height as f32,
+            0.0,
+            1.0,
+        );
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
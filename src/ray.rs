@@ -0,0 +1,30 @@
+use crate::util::Vec3;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn at_walks_along_direction() {
+        let ray = Ray::new(Vec3(1.0, 2.0, 3.0), Vec3(0.0, 0.0, -2.0));
+        assert_eq!(ray.at(0.0), ray.origin);
+        assert_eq!(ray.at(1.0), Vec3(1.0, 2.0, 1.0));
+        assert_eq!(ray.at(-0.5), Vec3(1.0, 2.0, 4.0));
+    }
+}
@@ -0,0 +1,585 @@
+use std::f32::consts::PI;
+
+use image::{ImageResult, Rgba32FImage};
+use rand::Rng;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
+    BufferBindingType, BufferUsages, Device, FilterMode, Queue, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderStages, Texture, TextureSampleType, TextureViewDescriptor,
+    TextureViewDimension,
+};
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    bvh::{Bvh, BvhNode},
+    camera::Camera,
+    geometry::{Geometry, GpuPrimitive, HitRecord},
+    material::{GpuMaterial, Lambertian, Material},
+    ray::Ray,
+    settings::RenderSettings,
+    util::{load_hdr_image, upload_hdr_image, Vec3},
+};
+
+/// `BOUNCE_T_MIN` in `compute.wgsl`: keeps bounced rays from hitting the surface they start on
+/// again due to rounding.
+const BOUNCE_T_MIN: f32 = 0.001;
+
+/// `SceneInfo` in `compute.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct SceneInfo {
+    primitive_count: u32,
+    has_environment: u32,
+    _pad: [u32; 2],
+}
+
+/// The camera and the geometry it looks at, together with the storage buffers the compute shader
+/// traces. Geometry and materials added to the scene only reach the GPU on the next
+/// `sync_to_gpu`.
+///
+/// Materials are referred to by the index `add_material` returns. Geometry added without one
+/// gets `DEFAULT_MATERIAL`, a grey diffuse surface every scene starts with.
+///
+/// The shader finds hits by walking a `Bvh` over the geometry, rebuilt whenever geometry is added.
+///
+/// Rays that miss every object sample the equirectangular environment map given to
+/// `set_environment`, or the sky gradient of `RenderSettings` while there is none.
+pub struct Scene {
+    pub camera: Camera,
+    /// Owns the geometry, in the order it was added, which `object_materials` follows.
+    bvh: Bvh,
+    object_materials: Vec<u32>,
+    materials: Vec<GpuMaterial>,
+    environment: Option<Rgba32FImage>,
+    has_environment: bool,
+    dirty: bool,
+    primitive_buffer: Buffer,
+    info_buffer: Buffer,
+    material_buffer: Buffer,
+    bvh_buffer: Buffer,
+    environment_texture: Texture,
+    environment_sampler: Sampler,
+    bind_group: BindGroup,
+}
+
+impl Scene {
+    pub const LAYOUT_ENTRIES: &'static [BindGroupLayoutEntry] = &[
+        BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 2,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 3,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 4,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 5,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ];
+
+    pub const DEFAULT_MATERIAL: u32 = 0;
+
+    pub fn new(device: &Device, queue: &Queue, camera: Camera) -> Self {
+        let materials = vec![Lambertian::new(Vec3(0.5, 0.5, 0.5)).to_gpu()];
+        let primitive_buffer = storage_buffer::<GpuPrimitive>(device, &[]);
+        let info_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&SceneInfo::zeroed()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let material_buffer = storage_buffer(device, &materials);
+        let bvh_buffer = storage_buffer::<BvhNode>(device, &[]);
+        // Bound until an environment is set, and never sampled.
+        let environment_texture = upload_hdr_image(device, queue, &Rgba32FImage::new(1, 1));
+        // Longitude wraps around, latitude stops at the poles.
+        let environment_sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group = bind_group(
+            device,
+            &primitive_buffer,
+            &info_buffer,
+            &material_buffer,
+            &bvh_buffer,
+            &environment_texture,
+            &environment_sampler,
+        );
+
+        Self {
+            camera,
+            bvh: Bvh::new(Vec::new()),
+            object_materials: Vec::new(),
+            materials,
+            environment: None,
+            has_environment: false,
+            dirty: false,
+            primitive_buffer,
+            info_buffer,
+            material_buffer,
+            bvh_buffer,
+            environment_texture,
+            environment_sampler,
+            bind_group,
+        }
+    }
+
+    pub fn bind_group_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: Self::LAYOUT_ENTRIES,
+        })
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = &dyn Geometry> {
+        self.bvh
+            .primitives()
+            .iter()
+            .map(|geometry| geometry.as_ref())
+    }
+
+    #[allow(dead_code)]
+    pub fn add(&mut self, geometry: Box<dyn Geometry>) {
+        self.add_with_material(geometry, Self::DEFAULT_MATERIAL);
+    }
+
+    pub fn add_with_material(&mut self, geometry: Box<dyn Geometry>, material: u32) {
+        debug_assert!(
+            (material as usize) < self.materials.len(),
+            "unknown material {}",
+            material
+        );
+        let mut objects = std::mem::replace(&mut self.bvh, Bvh::new(Vec::new())).into_primitives();
+        objects.push(geometry);
+        self.bvh = Bvh::new(objects);
+        self.object_materials.push(material);
+        self.dirty = true;
+    }
+
+    pub fn add_material(&mut self, material: &impl Material) -> u32 {
+        self.materials.push(material.to_gpu());
+        self.dirty = true;
+        self.materials.len() as u32 - 1
+    }
+
+    /// Loads an equirectangular image, preferably HDR (`.hdr`, `.exr`), to surround the scene
+    /// with. On error the current environment is kept.
+    pub fn set_environment(&mut self, path: &str) -> ImageResult<()> {
+        self.environment = Some(load_hdr_image(path)?);
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Uploads the camera, geometry, materials and environment if they changed since the last
+    /// call, and returns whether anything did. The buffers are rewritten in place while they are
+    /// big enough and only reallocated, along with the bind group, when they grow.
+    pub fn sync_to_gpu(&mut self, device: &Device, queue: &Queue) -> bool {
+        let camera_changed = self.camera.sync_to_gpu(queue);
+        if !self.dirty {
+            return camera_changed;
+        }
+        self.dirty = false;
+
+        let primitives: Vec<_> = self
+            .objects()
+            .zip(&self.object_materials)
+            .map(|(geometry, material)| GpuPrimitive {
+                material: *material,
+                ..geometry.to_gpu()
+            })
+            .collect();
+        let nodes = self.bvh.flatten();
+        let mut reallocated = upload(device, queue, &mut self.primitive_buffer, &primitives)
+            | upload(device, queue, &mut self.material_buffer, &self.materials)
+            | upload(device, queue, &mut self.bvh_buffer, &nodes);
+        if let Some(environment) = self.environment.take() {
+            self.environment_texture = upload_hdr_image(device, queue, &environment);
+            self.has_environment = true;
+            reallocated = true;
+        }
+        if reallocated {
+            self.bind_group = bind_group(
+                device,
+                &self.primitive_buffer,
+                &self.info_buffer,
+                &self.material_buffer,
+                &self.bvh_buffer,
+                &self.environment_texture,
+                &self.environment_sampler,
+            );
+        }
+        let info = SceneInfo {
+            primitive_count: primitives.len() as u32,
+            has_environment: self.has_environment as u32,
+            _pad: [0; 2],
+        };
+        queue.write_buffer(&self.info_buffer, 0, bytemuck::bytes_of(&info));
+        true
+    }
+
+    /// The closest object `ray` hits in front of its origin, as its index in the order objects
+    /// were added, along with the hit. Runs on the CPU against the scene's current geometry,
+    /// whether or not it was synced to the GPU yet.
+    #[allow(dead_code)]
+    pub fn raycast(&self, ray: &Ray) -> Option<(usize, HitRecord)> {
+        self.bvh.hit(ray, 0.0, f32::INFINITY)
+    }
+
+    /// The light `ray` brings back, as `trace` in `compute.wgsl` follows it: through up to
+    /// `settings.max_depth` bounces, adding the light emitted by every surface it hits and by the
+    /// sky it escapes to, each scaled by the attenuation so far. At a depth of 0 only the sky
+    /// and lights seen directly remain.
+    ///
+    /// The environment map is only sampled on the GPU; rays that escape here see
+    /// `RenderSettings::sky` even when the scene has one.
+    #[allow(dead_code)]
+    pub fn trace(&self, ray: &Ray, settings: &RenderSettings, rng: &mut impl Rng) -> Vec3 {
+        let mut ray = *ray;
+        let mut t_min = settings.near / ray.direction.length();
+        let mut attenuation = Vec3(1.0, 1.0, 1.0);
+        let mut color = Vec3::origin();
+        for depth in 0..=settings.max_depth {
+            let Some((object, hit)) = self.bvh.hit(&ray, t_min, f32::INFINITY) else {
+                color += attenuation * settings.sky(ray.direction);
+                break;
+            };
+            let material = &self.materials[self.object_materials[object] as usize];
+            color += attenuation * material.emitted();
+            if depth == settings.max_depth {
+                break;
+            }
+            let Some((scattered_attenuation, scattered)) = material.scatter(&ray, &hit, rng) else {
+                break;
+            };
+            attenuation = attenuation * scattered_attenuation;
+            ray = scattered;
+            t_min = BOUNCE_T_MIN;
+        }
+        color
+    }
+}
+
+/// Texture coordinates of `direction` in an equirectangular environment map, as `environment_uv`
+/// in `compute.wgsl` computes them. The mapping matches the equirectangular camera looking down
+/// -Z: that direction is at the centre of the image and +X a quarter of the width to its right.
+#[allow(dead_code)]
+pub fn environment_uv(direction: Vec3) -> (f32, f32) {
+    let direction = direction.normalize();
+    let longitude = direction.0.atan2(-direction.2);
+    let latitude = direction.1.clamp(-1.0, 1.0).asin();
+    (0.5 + longitude / (2.0 * PI), 0.5 - latitude / PI)
+}
+
+/// Storage bindings can't be empty, so an empty list still gets one unused element.
+fn storage_buffer<T: Pod + Default>(device: &Device, data: &[T]) -> Buffer {
+    let placeholder = [T::default()];
+    let data = if data.is_empty() { &placeholder } else { data };
+    device.create_buffer_init(&BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(data),
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+    })
+}
+
+/// Writes `data` to `buffer`, replacing the buffer if it is too small. Returns whether it did.
+fn upload<T: Pod + Default>(
+    device: &Device,
+    queue: &Queue,
+    buffer: &mut Buffer,
+    data: &[T],
+) -> bool {
+    if std::mem::size_of_val(data) as u64 > buffer.size() {
+        *buffer = storage_buffer(device, data);
+        true
+    } else {
+        if !data.is_empty() {
+            queue.write_buffer(buffer, 0, bytemuck::cast_slice(data));
+        }
+        false
+    }
+}
+
+fn bind_group(
+    device: &Device,
+    primitive_buffer: &Buffer,
+    info_buffer: &Buffer,
+    material_buffer: &Buffer,
+    bvh_buffer: &Buffer,
+    environment_texture: &Texture,
+    environment_sampler: &Sampler,
+) -> BindGroup {
+    let environment_view = environment_texture.create_view(&TextureViewDescriptor::default());
+    device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout: &Scene::bind_group_layout(device),
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Buffer(primitive_buffer.as_entire_buffer_binding()),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Buffer(info_buffer.as_entire_buffer_binding()),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::Buffer(material_buffer.as_entire_buffer_binding()),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: BindingResource::TextureView(&environment_view),
+            },
+            BindGroupEntry {
+                binding: 4,
+                resource: BindingResource::Sampler(environment_sampler),
+            },
+            BindGroupEntry {
+                binding: 5,
+                resource: BindingResource::Buffer(bvh_buffer.as_entire_buffer_binding()),
+            },
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use winit::dpi::PhysicalSize;
+
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{
+        geometry::{plane::Plane, sphere::Sphere},
+        material::{DiffuseLight, Metal},
+        util::test_device,
+    };
+
+    fn scene(device: &Device, queue: &Queue) -> Scene {
+        let camera = Camera::new(PhysicalSize::new(16, 9), device);
+        Scene::new(device, queue, camera)
+    }
+
+    #[test]
+    fn sync_sizes_buffers_to_the_geometry() {
+        let (device, queue) = test_device();
+        let mut scene = scene(&device, &queue);
+        for x in 0..3 {
+            scene.add(Box::new(Sphere::new(Vec3(x as f32, 0.0, -1.0), 0.5)));
+        }
+
+        assert!(scene.sync_to_gpu(&device, &queue));
+        assert_eq!(
+            scene.primitive_buffer.size(),
+            3 * size_of::<GpuPrimitive>() as u64
+        );
+        assert_eq!(scene.bvh_buffer.size(), 5 * size_of::<BvhNode>() as u64);
+        assert_eq!(scene.objects().count(), 3);
+    }
+
+    #[test]
+    fn sync_without_changes_does_nothing() {
+        let (device, queue) = test_device();
+        let mut scene = scene(&device, &queue);
+        scene.add(Box::new(Sphere::new(Vec3(0.0, 0.0, -1.0), 0.5)));
+        assert!(scene.sync_to_gpu(&device, &queue));
+        assert!(!scene.sync_to_gpu(&device, &queue));
+    }
+
+    #[test]
+    fn empty_scene_keeps_placeholder_elements() {
+        let (device, queue) = test_device();
+        let scene = scene(&device, &queue);
+        assert_eq!(
+            scene.primitive_buffer.size(),
+            size_of::<GpuPrimitive>() as u64
+        );
+        assert_eq!(scene.bvh_buffer.size(), size_of::<BvhNode>() as u64);
+    }
+
+    #[test]
+    fn raycast_returns_the_closest_object() {
+        let (device, queue) = test_device();
+        let mut scene = scene(&device, &queue);
+        scene.add(Box::new(Sphere::new(Vec3(0.0, 0.0, -1.0), 0.5)));
+        scene.add(Box::new(Sphere::new(Vec3(0.0, 0.0, -3.0), 0.5)));
+
+        let (index, hit) = scene
+            .raycast(&Ray::new(Vec3::origin(), -Vec3::k()))
+            .unwrap();
+        assert_eq!(index, 0);
+        assert!((hit.t - 0.5).abs() < 1e-6);
+
+        // From between the spheres, looking away from the first one.
+        let (index, hit) = scene
+            .raycast(&Ray::new(Vec3(0.0, 0.0, -2.0), -Vec3::k()))
+            .unwrap();
+        assert_eq!(index, 1);
+        assert!((hit.t - 0.5).abs() < 1e-6);
+
+        assert!(scene
+            .raycast(&Ray::new(Vec3::origin(), Vec3::j()))
+            .is_none());
+    }
+
+    fn settings(max_depth: u32) -> RenderSettings {
+        RenderSettings {
+            max_depth,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn trace_at_depth_0_sees_only_the_sky_and_lights() {
+        let (device, queue) = test_device();
+        let mut scene = scene(&device, &queue);
+        let lamp = scene.add_material(&DiffuseLight::new(Vec3(4.0, 4.0, 4.0)));
+        scene.add(Box::new(Sphere::new(Vec3(0.0, 0.0, -2.0), 0.5)));
+        scene.add_with_material(Box::new(Sphere::new(Vec3(0.0, 0.0, 2.0), 0.5)), lamp);
+
+        let settings = settings(0);
+        let mut rng = StdRng::seed_from_u64(527);
+        let up = Ray::new(Vec3::origin(), Vec3::j());
+        assert_eq!(
+            scene.trace(&up, &settings, &mut rng),
+            settings.sky(Vec3::j())
+        );
+        let at_grey_sphere = Ray::new(Vec3::origin(), -Vec3::k());
+        assert_eq!(
+            scene.trace(&at_grey_sphere, &settings, &mut rng),
+            Vec3::origin()
+        );
+        let at_lamp = Ray::new(Vec3::origin(), Vec3::k());
+        assert_eq!(
+            scene.trace(&at_lamp, &settings, &mut rng),
+            Vec3(4.0, 4.0, 4.0)
+        );
+    }
+
+    #[test]
+    fn trace_accumulates_emission_along_the_path() {
+        let (device, queue) = test_device();
+        let mut scene = scene(&device, &queue);
+        let mirror = scene.add_material(&Metal::new(Vec3(0.5, 0.25, 1.0), 0.0));
+        let lamp = scene.add_material(&DiffuseLight::new(Vec3(4.0, 4.0, 4.0)));
+        scene.add_with_material(Box::new(Plane::new(Vec3::origin(), Vec3::j())), mirror);
+        scene.add_with_material(Box::new(Sphere::new(Vec3(3.0, 2.0, 0.0), 0.5)), lamp);
+
+        // A black sky, so only the lamp lights the paths.
+        let settings = RenderSettings {
+            sky_top: Vec3::origin(),
+            sky_bottom: Vec3::origin(),
+            ..settings(8)
+        };
+        let mut rng = StdRng::seed_from_u64(519);
+        let direct = Ray::new(Vec3(3.0, 5.0, 0.0), -Vec3::j());
+        assert_eq!(
+            scene.trace(&direct, &settings, &mut rng),
+            Vec3(4.0, 4.0, 4.0)
+        );
+        let via_mirror = Ray::new(Vec3(-3.0, 2.0, 0.0), Vec3(3.0, -2.0, 0.0));
+        assert_eq!(
+            scene.trace(&via_mirror, &settings, &mut rng),
+            Vec3(2.0, 1.0, 4.0)
+        );
+        let missing = Ray::new(Vec3(0.0, 1.0, 0.0), Vec3::k());
+        assert_eq!(scene.trace(&missing, &settings, &mut rng), Vec3::origin());
+
+        // Without a bounce the mirror shows nothing of the lamp.
+        let settings = RenderSettings {
+            max_depth: 0,
+            ..settings
+        };
+        assert_eq!(
+            scene.trace(&via_mirror, &settings, &mut rng),
+            Vec3::origin()
+        );
+    }
+
+    fn assert_uv(direction: Vec3, expected: (f32, f32)) {
+        let (u, v) = environment_uv(direction);
+        assert!(
+            (u - expected.0).abs() < 1e-6 && (v - expected.1).abs() < 1e-6,
+            "{:?} maps to {:?}, not {:?}",
+            direction,
+            (u, v),
+            expected
+        );
+    }
+
+    #[test]
+    fn view_direction_is_the_centre_of_the_map() {
+        assert_eq!(environment_uv(-Vec3::k()).0, 0.5);
+        assert_uv(Vec3(0.0, 0.0, -7.0), (0.5, 0.5));
+    }
+
+    #[test]
+    fn longitude_runs_left_to_right() {
+        assert_uv(Vec3::i(), (0.75, 0.5));
+        assert_uv(-Vec3::i(), (0.25, 0.5));
+        assert_uv(Vec3::k(), (1.0, 0.5));
+    }
+
+    #[test]
+    fn latitude_runs_top_to_bottom() {
+        assert_eq!(environment_uv(Vec3::j()).1, 0.0);
+        assert_eq!(environment_uv(-Vec3::j()).1, 1.0);
+        assert_uv(Vec3(0.0, 1.0, -1.0), (0.5, 0.25));
+    }
+}
@@ -0,0 +1,180 @@
+use std::{fmt::Display, str::FromStr};
+
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    Buffer, BufferUsages, Device,
+};
+use winit::dpi::PhysicalSize;
+
+use crate::util::TileSize;
+
+/// Order in which workgroup indices are handed out to the image tiles.
+///
+/// `Linear` walks the tiles row by row. `Morton` follows a Z-order curve, so tiles that are
+/// dispatched close together also lie close together on screen and share more of the texture
+/// and scene data in the caches. The GPU is still free to schedule workgroups as it likes, so
+/// neither order makes the processing deterministic; it only biases which tiles run together.
+/// Compare the two with the frame-time stats (T) or a `--tile` sweep.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileOrder {
+    #[default]
+    Linear,
+    Morton,
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(TileOrder::Linear),
+            "morton" | "z" => Ok(TileOrder::Morton),
+            _ => Err(format!(
+                "unknown tile order `{}`, expected linear or morton",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for TileOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TileOrder::Linear => write!(f, "linear"),
+            TileOrder::Morton => write!(f, "morton"),
+        }
+    }
+}
+
+/// Tile coordinates in dispatch order, packed as `x | y << 16`. The compute shader looks up its
+/// flattened workgroup index in this list to find the tile it renders. The buffer is bound with
+/// the image textures since, like them, it changes with the image size.
+///
+/// Because of that lookup the dispatch doesn't need the shape of the tile grid. When the grid is
+/// wider or taller than `max_compute_workgroups_per_dimension`, the tiles are dispatched as rows
+/// of that many workgroups instead, and the surplus workgroups of the last row exit early.
+pub struct TileSchedule {
+    order: TileOrder,
+    workgroups: (u32, u32),
+    buffer: Buffer,
+}
+
+impl TileSchedule {
+    pub fn new(
+        device: &Device,
+        order: TileOrder,
+        tile_size: TileSize,
+        image_size: PhysicalSize<u32>,
+    ) -> Self {
+        let grid = tile_size.workgroups(image_size);
+        let tiles = tiles(order, grid);
+
+        let max = device.limits().max_compute_workgroups_per_dimension;
+        let workgroups = if grid.0 <= max && grid.1 <= max {
+            grid
+        } else {
+            let count = tiles.len() as u32;
+            let workgroups = (count.min(max), count.div_ceil(max));
+            eprintln!(
+                "{}x{} tiles exceed the {} workgroups per dimension limit, dispatching {}x{}",
+                grid.0, grid.1, max, workgroups.0, workgroups.1
+            );
+            workgroups
+        };
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&tiles),
+            usage: BufferUsages::STORAGE,
+        });
+
+        Self {
+            order,
+            workgroups,
+            buffer,
+        }
+    }
+
+    pub fn order(&self) -> TileOrder {
+        self.order
+    }
+
+    pub fn workgroups(&self) -> (u32, u32) {
+        self.workgroups
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+}
+
+fn tiles(order: TileOrder, (columns, rows): (u32, u32)) -> Vec<u32> {
+    let mut tiles: Vec<(u32, u32)> = (0..rows)
+        .flat_map(|y| (0..columns).map(move |x| (x, y)))
+        .collect();
+    if order == TileOrder::Morton {
+        tiles.sort_by_key(|&(x, y)| spread_bits(x) | spread_bits(y) << 1);
+    }
+    tiles.into_iter().map(|(x, y)| x | y << 16).collect()
+}
+
+/// Moves bit `n` of a 16-bit value to bit `2n`, leaving the odd bits for the other coordinate.
+fn spread_bits(value: u32) -> u32 {
+    let mut value = value & 0xffff;
+    value = (value | value << 8) & 0x00ff_00ff;
+    value = (value | value << 4) & 0x0f0f_0f0f;
+    value = (value | value << 2) & 0x3333_3333;
+    (value | value << 1) & 0x5555_5555
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unpack(tiles: &[u32]) -> Vec<(u32, u32)> {
+        tiles
+            .iter()
+            .map(|tile| (tile & 0xffff, tile >> 16))
+            .collect()
+    }
+
+    #[test]
+    fn linear_order_walks_rows() {
+        assert_eq!(
+            unpack(&tiles(TileOrder::Linear, (3, 2))),
+            [(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn morton_order_follows_the_z_curve() {
+        // Position of each tile of a 4x4 grid in the dispatch order, row by row.
+        let expected = [[0, 1, 4, 5], [2, 3, 6, 7], [8, 9, 12, 13], [10, 11, 14, 15]];
+        let morton = unpack(&tiles(TileOrder::Morton, (4, 4)));
+        assert_eq!(morton.len(), 16);
+        for (i, (x, y)) in morton.into_iter().enumerate() {
+            assert_eq!(expected[y as usize][x as usize], i, "tile ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn morton_order_is_a_permutation_of_linear_order() {
+        for grid in [(4, 4), (5, 3), (1, 7), (33, 17)] {
+            let mut morton = tiles(TileOrder::Morton, grid);
+            morton.sort();
+            let mut linear = tiles(TileOrder::Linear, grid);
+            linear.sort();
+            assert_eq!(morton, linear, "{:?}", grid);
+        }
+    }
+
+    #[test]
+    fn spread_bits_interleaves_with_zeros() {
+        assert_eq!(spread_bits(0), 0);
+        assert_eq!(spread_bits(1), 1);
+        assert_eq!(spread_bits(0b11), 0b101);
+        assert_eq!(spread_bits(0b1010), 0b100_0100);
+        assert_eq!(spread_bits(0xffff), 0x5555_5555);
+        // Only the low 16 bits are kept.
+        assert_eq!(spread_bits(0x1_0001), 1);
+    }
+}
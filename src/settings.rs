@@ -0,0 +1,410 @@
+use std::{fmt::Display, str::FromStr};
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages,
+    Device, Queue, ShaderStages,
+};
+
+use crate::util::{Vec3, Vec3Uniform};
+
+/// Curve that compresses the radiance of the accumulated image, which emissive surfaces push past
+/// 1.0, into the displayable range before gamma encoding. Applied by `frag_main` in
+/// `shader.wgsl`; `apply` is the reference for one channel.
+///
+/// `Reinhard` is `c / (c + 1)`, which never quite reaches white. `Aces` is Narkowicz's fit of the
+/// ACES filmic curve, with more contrast and a shoulder that saturates at around 10.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMap {
+    #[default]
+    None,
+    Reinhard,
+    Aces,
+}
+
+impl ToneMap {
+    #[allow(dead_code)]
+    pub fn apply(self, c: f32) -> f32 {
+        match self {
+            ToneMap::None => c,
+            ToneMap::Reinhard => c / (c + 1.0),
+            ToneMap::Aces => {
+                ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+        }
+    }
+
+    /// `ToneMap` in `shader.wgsl`.
+    pub fn as_uniform(self) -> u32 {
+        match self {
+            ToneMap::None => 0,
+            ToneMap::Reinhard => 1,
+            ToneMap::Aces => 2,
+        }
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ToneMap::None),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            _ => Err(format!(
+                "unknown tone map `{}`, expected none, reinhard or aces",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for ToneMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToneMap::None => write!(f, "none"),
+            ToneMap::Reinhard => write!(f, "reinhard"),
+            ToneMap::Aces => write!(f, "aces"),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct RenderSettingsUniform {
+    frame_index: u32,
+    dither: u32,
+    near: f32,
+    samples_per_pixel: u32,
+    frame_count: u32,
+    max_depth: u32,
+    _pad: [u32; 2],
+    sky_top: Vec3Uniform,
+    sky_bottom: Vec3Uniform,
+}
+
+/// Per-frame knobs of the compute pass, mirrored by `RenderSettings` in `compute.wgsl`.
+///
+/// `dither` adds ±0.5 LSB of blue noise before the image is quantised to 8 bits, which breaks up
+/// banding in smooth gradients such as the sky; the noise itself sits below one code value so
+/// it is not visible as grain. The compute pass dithers the 8-bit image `--direct-present`
+/// copies, and the render pass, through `DisplaySettings`, the image it draws from the
+/// accumulation.
+///
+/// `near` is the distance from the camera below which geometry is clipped, like a rasteriser's
+/// near plane: a camera that moves into a sphere sees its inside rather than artifacts from rays
+/// starting on the surface. It is measured along each ray, so it is really a small sphere around
+/// the camera. The default of 0.001 only rejects hits at the camera origin itself.
+///
+/// `samples_per_pixel` rays are traced through random points of every pixel and averaged, which
+/// smooths edges and path tracing noise at a proportional cost. A single sample goes through the
+/// pixel centre.
+///
+/// `frame_count` is the number of frames averaged into the accumulated image, including the one
+/// being rendered: each frame is blended in with a weight of `1 / frame_count`, so at 1 the image
+/// starts over. Anything that changes what the image shows must `reset_accumulation`.
+///
+/// `max_depth` is the number of times a path may scatter before it is cut off and contributes no
+/// more light. Higher values brighten scenes with many interreflections, such as glass or facing
+/// mirrors, at the cost of longer paths.
+///
+/// `sky_top` and `sky_bottom` are the colours of the background that rays which miss every object
+/// pick up, for rays pointing straight up and straight down; in between they are blended by the
+/// height of the ray direction. The sky is the only light in a scene without emissive materials.
+///
+/// `tone_map` isn't used by the compute pass: the render pass applies it, through
+/// `DisplaySettings`, so that the accumulated image keeps its full range.
+///
+/// The settings are plain values; the uniform buffer they are uploaded to belongs to the caller,
+/// which creates it with `create_buffer` and refreshes it with `upload`.
+pub struct RenderSettings {
+    pub frame_index: u32,
+    pub dither: bool,
+    pub near: f32,
+    pub samples_per_pixel: u32,
+    pub frame_count: u32,
+    pub max_depth: u32,
+    pub sky_top: Vec3,
+    pub sky_bottom: Vec3,
+    pub tone_map: ToneMap,
+}
+
+impl RenderSettings {
+    pub const DEFAULT_NEAR: f32 = 0.001;
+    pub const DEFAULT_SAMPLES_PER_PIXEL: u32 = 16;
+    pub const DEFAULT_MAX_DEPTH: u32 = 8;
+    pub const DEFAULT_SKY_TOP: Vec3 = Vec3(0.1, 0.3, 1.0);
+    pub const DEFAULT_SKY_BOTTOM: Vec3 = Vec3(0.8, 0.9, 1.0);
+
+    pub const LAYOUT_ENTRIES: &'static [BindGroupLayoutEntry] = &[BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }];
+
+    pub fn reset_accumulation(&mut self) {
+        self.frame_count = 0;
+    }
+
+    /// Moves on to the next frame: a new random sequence, and one more frame in the average.
+    pub fn advance_frame(&mut self) {
+        self.frame_index = self.frame_index.wrapping_add(1);
+        self.frame_count = self.frame_count.saturating_add(1);
+    }
+
+    /// The weight the frame being rendered gets in the accumulated image, as `main` in
+    /// `compute.wgsl` blends it in.
+    #[allow(dead_code)]
+    pub fn accumulation_weight(&self) -> f32 {
+        1.0 / self.frame_count.max(1) as f32
+    }
+
+    /// Whether the samples of a pixel go through random points of it rather than its centre, as
+    /// `main` in `compute.wgsl` decides: a lone sample stays in the centre until later frames
+    /// are averaged in.
+    #[allow(dead_code)]
+    pub fn jitters_samples(&self) -> bool {
+        self.samples_per_pixel > 1 || self.frame_count > 1
+    }
+
+    /// The background colour in `direction`, as `sky` in `compute.wgsl` computes it.
+    pub fn sky(&self, direction: Vec3) -> Vec3 {
+        let a = (direction.normalize().1 + 1.0) * 0.5;
+        self.sky_bottom * (1.0 - a) + self.sky_top * a
+    }
+
+    fn as_uniform(&self) -> RenderSettingsUniform {
+        RenderSettingsUniform {
+            frame_index: self.frame_index,
+            dither: self.dither as u32,
+            near: self.near,
+            samples_per_pixel: self.samples_per_pixel,
+            frame_count: self.frame_count,
+            max_depth: self.max_depth,
+            _pad: [0; 2],
+            sky_top: self.sky_top.to_uniform(),
+            sky_bottom: self.sky_bottom.to_uniform(),
+        }
+    }
+
+    pub fn bind_group_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: Self::LAYOUT_ENTRIES,
+        })
+    }
+
+    pub fn bind_group(device: &Device, buffer: &Buffer) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &Self::bind_group_layout(device),
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Buffer(buffer.as_entire_buffer_binding()),
+            }],
+        })
+    }
+
+    /// A uniform buffer holding the current settings.
+    pub fn create_buffer(&self, device: &Device) -> Buffer {
+        device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&self.as_uniform()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        })
+    }
+
+    pub fn upload(&self, queue: &Queue, buffer: &Buffer) {
+        queue.write_buffer(buffer, 0, bytemuck::bytes_of(&self.as_uniform()));
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            frame_index: 0,
+            dither: true,
+            near: Self::DEFAULT_NEAR,
+            samples_per_pixel: Self::DEFAULT_SAMPLES_PER_PIXEL,
+            frame_count: 0,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+            sky_top: Self::DEFAULT_SKY_TOP,
+            sky_bottom: Self::DEFAULT_SKY_BOTTOM,
+            tone_map: ToneMap::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use wgpu::{BufferDescriptor, CommandEncoderDescriptor, Maintain, MapMode};
+
+    use super::*;
+    use crate::util::test_device;
+
+    fn settings() -> RenderSettings {
+        RenderSettings::default()
+    }
+
+    #[test]
+    fn samples_per_pixel_reach_the_uniform() {
+        let mut settings = settings();
+        assert_eq!(
+            settings.as_uniform().samples_per_pixel,
+            RenderSettings::DEFAULT_SAMPLES_PER_PIXEL
+        );
+        settings.samples_per_pixel = 64;
+        assert_eq!(settings.as_uniform().samples_per_pixel, 64);
+    }
+
+    #[test]
+    fn single_sample_starts_in_the_pixel_centre() {
+        let mut settings = settings();
+        settings.samples_per_pixel = 1;
+        settings.frame_count = 1;
+        assert!(!settings.jitters_samples());
+        // Once a second frame is averaged in, the samples spread over the pixel.
+        settings.frame_count = 2;
+        assert!(settings.jitters_samples());
+
+        settings.samples_per_pixel = 2;
+        settings.frame_count = 1;
+        assert!(settings.jitters_samples());
+    }
+
+    #[test]
+    fn upload_writes_the_uniform() {
+        let (device, queue) = test_device();
+        let mut settings = settings();
+        let size = size_of::<RenderSettingsUniform>() as u64;
+        assert_eq!(settings.create_buffer(&device).size(), size);
+
+        // `create_buffer` doesn't allow copies out of the buffer, so upload to one that does.
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size,
+            usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        settings.samples_per_pixel = 64;
+        settings.max_depth = 3;
+        settings.upload(&queue, &buffer);
+
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_buffer_to_buffer(&buffer, 0, &readback, 0, size);
+        queue.submit([encoder.finish()]);
+        readback
+            .slice(..)
+            .map_async(MapMode::Read, |result| result.unwrap());
+        device.poll(Maintain::Wait);
+
+        let uniform: RenderSettingsUniform =
+            bytemuck::pod_read_unaligned(&readback.slice(..).get_mapped_range());
+        assert_eq!(
+            bytemuck::bytes_of(&uniform),
+            bytemuck::bytes_of(&settings.as_uniform())
+        );
+        assert_eq!(uniform.samples_per_pixel, 64);
+        assert_eq!(uniform.max_depth, 3);
+    }
+
+    #[test]
+    fn frame_count_reaches_the_uniform_and_resets() {
+        let mut settings = settings();
+        assert_eq!(settings.as_uniform().frame_count, 0);
+        for _ in 0..5 {
+            settings.advance_frame();
+        }
+        assert_eq!(settings.as_uniform().frame_count, 5);
+        assert_eq!(settings.as_uniform().frame_index, 5);
+
+        // After a reset the next frames count up from 1 again, each weighted so that the
+        // accumulated image is the plain average of the frames since.
+        settings.reset_accumulation();
+        assert_eq!(settings.as_uniform().frame_count, 0);
+        for expected in 1..=4 {
+            settings.advance_frame();
+            assert_eq!(settings.as_uniform().frame_count, expected);
+            assert_eq!(settings.accumulation_weight(), 1.0 / expected as f32);
+        }
+        // The random sequence carries on rather than repeating the frames before the reset.
+        assert_eq!(settings.as_uniform().frame_index, 9);
+    }
+
+    #[test]
+    fn frame_counters_stay_in_range() {
+        let mut settings = settings();
+        settings.frame_index = u32::MAX;
+        settings.frame_count = u32::MAX;
+        settings.advance_frame();
+        assert_eq!(settings.frame_index, 0);
+        assert_eq!(settings.frame_count, u32::MAX);
+    }
+
+    #[test]
+    fn max_depth_reaches_the_uniform() {
+        let mut settings = settings();
+        assert_eq!(
+            settings.as_uniform().max_depth,
+            RenderSettings::DEFAULT_MAX_DEPTH
+        );
+        settings.max_depth = 50;
+        assert_eq!(settings.as_uniform().max_depth, 50);
+    }
+
+    #[test]
+    fn tone_maps_keep_black_and_rise_monotonically() {
+        for tone_map in [ToneMap::None, ToneMap::Reinhard, ToneMap::Aces] {
+            assert_eq!(tone_map.apply(0.0), 0.0, "{}", tone_map);
+            let mut previous = 0.0;
+            for step in 1..=10_000 {
+                let value = tone_map.apply(step as f32 * 0.01);
+                assert!(value >= previous, "{} at {}", tone_map, step);
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn tone_maps_compress_into_the_display_range() {
+        assert_eq!(ToneMap::Reinhard.apply(1.0), 0.5);
+        assert!(ToneMap::Reinhard.apply(1000.0) < 1.0);
+        assert_eq!(ToneMap::Aces.apply(100.0), 1.0);
+        assert!((ToneMap::Aces.apply(0.18) - 0.267).abs() < 1e-3);
+        assert_eq!(ToneMap::None.apply(4.0), 4.0);
+    }
+
+    #[test]
+    fn tone_map_names_round_trip() {
+        for tone_map in [ToneMap::None, ToneMap::Reinhard, ToneMap::Aces] {
+            assert_eq!(tone_map.to_string().parse(), Ok(tone_map));
+        }
+        assert!("filmic".parse::<ToneMap>().is_err());
+    }
+
+    #[test]
+    fn sky_blends_from_bottom_to_top() {
+        let mut settings = settings();
+        settings.sky_top = Vec3(0.0, 0.0, 1.0);
+        settings.sky_bottom = Vec3(1.0, 1.0, 1.0);
+        assert_eq!(settings.sky(Vec3(0.0, 3.0, 0.0)), settings.sky_top);
+        assert_eq!(settings.sky(Vec3(0.0, -0.5, 0.0)), settings.sky_bottom);
+        assert_eq!(settings.sky(Vec3(2.0, 0.0, -1.0)), Vec3(0.5, 0.5, 1.0));
+    }
+}
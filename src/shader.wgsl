@@ -4,6 +4,76 @@ var compute_tex: texture_2d<f32>;
 @group(0) @binding(1)
 var compute_sampler: sampler;
 
+struct LutParams {
+    domain_min: vec3f,
+    enabled: u32,
+    domain_max: vec3f,
+    size: f32,
+}
+
+@group(1) @binding(0)
+var lut_tex: texture_3d<f32>;
+@group(1) @binding(1)
+var lut_sampler: sampler;
+@group(1) @binding(2)
+var<uniform> lut: LutParams;
+
+fn grade(color: vec3f) -> vec3f {
+    let t = clamp((color - lut.domain_min) / (lut.domain_max - lut.domain_min), vec3f(0.0), vec3f(1.0));
+    // Sample texel centres so the table's first and last entries map exactly to the domain ends.
+    let uvw = (t * (lut.size - 1.0) + 0.5) / lut.size;
+    return textureSampleLevel(lut_tex, lut_sampler, uvw, 0.0).rgb;
+}
+
+// What happens to the linear colour before it is written: nothing, sRGB encoding, or sRGB decoding
+// to cancel out the encoding of an sRGB target when gamma is turned off. See `DisplaySettings`.
+const TRANSFER_NONE: u32 = 0u;
+const TRANSFER_ENCODE: u32 = 1u;
+const TRANSFER_DECODE: u32 = 2u;
+
+const TONE_MAP_NONE: u32 = 0u;
+const TONE_MAP_REINHARD: u32 = 1u;
+const TONE_MAP_ACES: u32 = 2u;
+
+struct Display {
+    transfer: u32,
+    tone_map: u32,
+    dither: u32,
+    target_srgb: u32,
+}
+
+@group(2) @binding(0)
+var<uniform> display: Display;
+@group(2) @binding(1)
+var blue_noise_tex: texture_2d<f32>;
+
+// Narkowicz's fit of the ACES filmic curve. The tone maps mirror `ToneMap::apply`.
+fn aces(c: vec3f) -> vec3f {
+    return clamp((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14), vec3f(0.0), vec3f(1.0));
+}
+
+// Mirrors `display::srgb_encode`.
+fn srgb_encode(linear: vec3f) -> vec3f {
+    let c = clamp(linear, vec3f(0.0), vec3f(1.0));
+    return select(1.055 * pow(c, vec3f(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3f(0.0031308));
+}
+
+fn srgb_decode(encoded: vec3f) -> vec3f {
+    let c = clamp(encoded, vec3f(0.0), vec3f(1.0));
+    return select(pow((c + 0.055) / 1.055, vec3f(2.4)), c / 12.92, c <= vec3f(0.04045));
+}
+
+// Adds +-0.5 LSB of blue noise so smooth gradients don't band, in the space the target quantises
+// in. Unlike the compute pass's, the pattern stays put, so a converged image doesn't shimmer.
+fn dither(rgb: vec3f, pixel: vec2u) -> vec3f {
+    let size = textureDimensions(blue_noise_tex);
+    let noise = (textureLoad(blue_noise_tex, pixel % size, 0).r - 0.5) / 255.0;
+    if display.target_srgb != 0u {
+        return srgb_decode(srgb_encode(rgb) + noise);
+    }
+    return rgb + noise;
+}
+
 struct VertexOut {
     @builtin(position) pos: vec4f,
     @location(0) tex_coord: vec2f,
@@ -37,5 +107,32 @@ fn vert_main(
 fn frag_main(
     vert_out: VertexOut,
 ) -> @location(0) vec4f {
-    return textureSample(compute_tex, compute_sampler, vert_out.tex_coord);
+    let color = textureSample(compute_tex, compute_sampler, vert_out.tex_coord);
+    var rgb = color.rgb;
+    switch display.tone_map {
+        case TONE_MAP_REINHARD: {
+            rgb = rgb / (rgb + 1.0);
+        }
+        case TONE_MAP_ACES: {
+            rgb = aces(rgb);
+        }
+        default: {}
+    }
+    // Grades are authored for display-encoded input and produce display-encoded output.
+    if lut.enabled != 0u {
+        rgb = srgb_decode(grade(srgb_encode(rgb)));
+    }
+    switch display.transfer {
+        case TRANSFER_ENCODE: {
+            rgb = srgb_encode(rgb);
+        }
+        case TRANSFER_DECODE: {
+            rgb = srgb_decode(rgb);
+        }
+        default: {}
+    }
+    if display.dither != 0u {
+        rgb = dither(rgb, vec2u(vert_out.pos.xy));
+    }
+    return vec4f(rgb, color.a);
 }
@@ -1,20 +1,28 @@
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::{
+    fmt::Display,
+    ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign},
+    path::Path,
+    str::FromStr,
+};
 
-use image::GenericImageView;
+use bytemuck::{Pod, Zeroable};
+use half::f16;
+use image::{ImageResult, Rgba32FImage, RgbaImage};
+use rand::Rng;
 use wgpu::{
     include_wgsl,
     util::{DeviceExt, TextureDataOrder},
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, ColorTargetState, ColorWrites,
-    ComputePipeline, ComputePipelineDescriptor, Device, Extent3d, MultisampleState,
-    PipelineCompilationOptions, PipelineLayoutDescriptor, PrimitiveState, Queue, RenderPipeline,
-    RenderPipelineDescriptor, Sampler, SamplerBindingType, ShaderStages, StorageTextureAccess,
-    Texture, TextureDescriptor, TextureFormat, TextureUsages, TextureViewDescriptor,
-    TextureViewDimension,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, ColorTargetState,
+    ColorWrites, ComputePipeline, ComputePipelineDescriptor, Device, Extent3d, Limits,
+    MultisampleState, PipelineCompilationOptions, PipelineLayoutDescriptor, PrimitiveState, Queue,
+    RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType, ShaderModuleDescriptor,
+    ShaderSource, ShaderStages, StorageTextureAccess, Texture, TextureDescriptor, TextureFormat,
+    TextureUsages, TextureViewDescriptor, TextureViewDimension,
 };
-use winit::dpi::PhysicalSize;
+use winit::{dpi::PhysicalSize, window::Icon};
 
-#[derive(Default, Clone, Copy, Debug)]
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
 pub struct Vec3(pub f32, pub f32, pub f32);
 
 impl Vec3 {
@@ -35,6 +43,190 @@ impl Vec3 {
     pub fn as_array(&self) -> [f32; 3] {
         [self.0, self.1, self.2]
     }
+
+    pub fn to_uniform(self) -> Vec3Uniform {
+        Vec3Uniform {
+            xyz: self.as_array(),
+            _pad: 0.0,
+        }
+    }
+
+    /// Builds a vector from the components at indices `i`, `j` and `k` (0 = x, 1 = y, 2 = z).
+    /// Panics if an index is above 2.
+    pub fn swizzle(&self, i: usize, j: usize, k: usize) -> Vec3 {
+        Vec3(self[i], self[j], self[k])
+    }
+
+    #[allow(dead_code)]
+    pub fn yzx(&self) -> Vec3 {
+        self.swizzle(1, 2, 0)
+    }
+
+    #[allow(dead_code)]
+    pub fn zxy(&self) -> Vec3 {
+        self.swizzle(2, 0, 1)
+    }
+
+    /// Same as `*`, for when a component-wise product reads better spelled out, e.g. tinting a
+    /// colour by an albedo.
+    #[allow(dead_code)]
+    pub fn component_mul(&self, rhs: Vec3) -> Vec3 {
+        *self * rhs
+    }
+
+    pub fn dot(&self, rhs: Vec3) -> f32 {
+        self.0 * rhs.0 + self.1 * rhs.1 + self.2 * rhs.2
+    }
+
+    pub fn cross(&self, rhs: Vec3) -> Vec3 {
+        Vec3(
+            self.1 * rhs.2 - self.2 * rhs.1,
+            self.2 * rhs.0 - self.0 * rhs.2,
+            self.0 * rhs.1 - self.1 * rhs.0,
+        )
+    }
+
+    pub fn length_squared(&self) -> f32 {
+        self.dot(*self)
+    }
+
+    pub fn length(&self) -> f32 {
+        self.length_squared().sqrt()
+    }
+
+    /// The zero vector has no direction and stays the zero vector.
+    pub fn normalize(&self) -> Vec3 {
+        let length = self.length();
+        if length == 0.0 {
+            Vec3::origin()
+        } else {
+            *self / length
+        }
+    }
+
+    pub fn near_zero(&self) -> bool {
+        const EPSILON: f32 = 1e-8;
+        self.0.abs() < EPSILON && self.1.abs() < EPSILON && self.2.abs() < EPSILON
+    }
+
+    /// Uniformly distributed inside the unit sphere, by rejection sampling the enclosing cube.
+    pub fn random_in_unit_sphere(rng: &mut impl Rng) -> Vec3 {
+        loop {
+            let p = Vec3(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            if p.length_squared() < 1.0 {
+                return p;
+            }
+        }
+    }
+
+    /// Uniformly distributed on the unit sphere.
+    pub fn random_unit_vector(rng: &mut impl Rng) -> Vec3 {
+        loop {
+            let p = Vec3::random_in_unit_sphere(rng);
+            if !p.near_zero() {
+                return p.normalize();
+            }
+        }
+    }
+
+    /// Mirrors `self` about the plane with unit `normal`.
+    pub fn reflect(&self, normal: Vec3) -> Vec3 {
+        *self - normal * (2.0 * self.dot(normal))
+    }
+
+    /// Bends unit vector `self` through a surface with unit `normal` facing against it, by Snell's
+    /// law. Under total internal reflection the result is meaningless but finite; callers are
+    /// expected to check for it and reflect instead.
+    pub fn refract(&self, normal: Vec3, etai_over_etat: f32) -> Vec3 {
+        let cos_theta = (-*self).dot(normal).min(1.0);
+        let out_perpendicular = (*self + normal * cos_theta) * etai_over_etat;
+        let out_parallel = normal * -(1.0 - out_perpendicular.length_squared()).abs().sqrt();
+        out_perpendicular + out_parallel
+    }
+
+    /// In radians, between 0 and π.
+    #[allow(dead_code)]
+    pub fn angle_between(&self, other: &Vec3) -> f32 {
+        let cos = self.dot(*other) / (self.length() * other.length());
+        cos.clamp(-1.0, 1.0).acos()
+    }
+
+    /// Rotates counter-clockwise when looking down `axis` towards the origin (Rodrigues' rotation
+    /// formula). `axis` doesn't need to be normalised.
+    #[allow(dead_code)]
+    pub fn rotate_around(&self, axis: &Vec3, radians: f32) -> Vec3 {
+        let axis = axis.normalize();
+        let (sin, cos) = radians.sin_cos();
+        *self * cos + axis.cross(*self) * sin + axis * (axis.dot(*self) * (1.0 - cos))
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl MulAssign<f32> for Vec3 {
+    fn mul_assign(&mut self, rhs: f32) {
+        *self = *self * rhs;
+    }
+}
+
+impl DivAssign<f32> for Vec3 {
+    fn div_assign(&mut self, rhs: f32) {
+        *self = *self / rhs;
+    }
+}
+
+impl Index<usize> for Vec3 {
+    type Output = f32;
+
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.0,
+            1 => &self.1,
+            2 => &self.2,
+            _ => panic!(
+                "Vec3 axis index out of bounds: {} (expected 0, 1 or 2)",
+                axis
+            ),
+        }
+    }
+}
+
+impl IndexMut<usize> for Vec3 {
+    fn index_mut(&mut self, axis: usize) -> &mut Self::Output {
+        match axis {
+            0 => &mut self.0,
+            1 => &mut self.1,
+            2 => &mut self.2,
+            _ => panic!(
+                "Vec3 axis index out of bounds: {} (expected 0, 1 or 2)",
+                axis
+            ),
+        }
+    }
+}
+
+/// A `Vec3` as WGSL lays out `vec3<f32>` in a uniform buffer: 16-byte aligned, with the fourth
+/// component as padding. Upload this rather than `as_array`, whose 12 bytes shift every field
+/// that follows.
+#[repr(C, align(16))]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct Vec3Uniform {
+    pub xyz: [f32; 3],
+    _pad: f32,
 }
 
 impl Add for Vec3 {
@@ -69,6 +261,14 @@ impl Mul<f32> for Vec3 {
     }
 }
 
+impl Mul for Vec3 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self(self.0 * rhs.0, self.1 * rhs.1, self.2 * rhs.2)
+    }
+}
+
 impl Div<f32> for Vec3 {
     type Output = Self;
 
@@ -77,6 +277,122 @@ impl Div<f32> for Vec3 {
     }
 }
 
+/// Pixel footprint of one compute workgroup.
+///
+/// WGSL in this wgpu version can't take `override` constants in `@workgroup_size`, so the
+/// compute shader is specialised per size by rewriting its `TILE_WIDTH`/`TILE_HEIGHT` constants.
+/// A size is valid when both sides are at least 1, each side fits
+/// `max_compute_workgroup_size_x/y` and `width * height` fits
+/// `max_compute_invocations_per_workgroup` (256 on the default limits, so 8x8, 16x16 and 32x8
+/// are fine but 32x32 is not).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TileSize {
+    pub const DEFAULT: Self = Self {
+        width: 8,
+        height: 8,
+    };
+
+    pub fn fits(&self, limits: &Limits) -> bool {
+        self.width >= 1
+            && self.height >= 1
+            && self.width <= limits.max_compute_workgroup_size_x
+            && self.height <= limits.max_compute_workgroup_size_y
+            && self.width * self.height <= limits.max_compute_invocations_per_workgroup
+    }
+
+    pub fn workgroups(&self, size: PhysicalSize<u32>) -> (u32, u32) {
+        (
+            size.width.div_ceil(self.width),
+            size.height.div_ceil(self.height),
+        )
+    }
+}
+
+impl Default for TileSize {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl FromStr for TileSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = || {
+            let (width, height) = s.split_once('x')?;
+            Some(Self {
+                width: width.trim().parse().ok()?,
+                height: height.trim().parse().ok()?,
+            })
+        };
+        parse()
+            .filter(|tile| tile.width > 0 && tile.height > 0)
+            .ok_or_else(|| format!("invalid tile size `{}`, expected WxH", s))
+    }
+}
+
+impl Display for TileSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// Region of the window the rendered image is presented in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ViewRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ViewRect {
+    pub fn full(size: PhysicalSize<u32>) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: size.width,
+            height: size.height,
+        }
+    }
+
+    /// Largest rectangle of `aspect` (width / height) centred in `size`, leaving bars on the
+    /// sides or at the top and bottom.
+    pub fn letterbox(size: PhysicalSize<u32>, aspect: f32) -> Self {
+        let width = (size.height as f32 * aspect).round() as u32;
+        let (width, height) = if width <= size.width {
+            (width, size.height)
+        } else {
+            (size.width, (size.width as f32 / aspect).round() as u32)
+        };
+        let width = width.clamp(1, size.width.max(1));
+        let height = height.clamp(1, size.height.max(1));
+
+        Self {
+            x: size.width.saturating_sub(width) / 2,
+            y: size.height.saturating_sub(height) / 2,
+            width,
+            height,
+        }
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.width, self.height)
+    }
+
+    /// Converts a window position into a pixel of the rect, if it falls inside it.
+    pub fn local_pixel(&self, x: f64, y: f64) -> Option<(u32, u32)> {
+        let (x, y) = (x - self.x as f64, y - self.y as f64);
+        (x >= 0.0 && y >= 0.0 && x < self.width as f64 && y < self.height as f64)
+            .then_some((x as u32, y as u32))
+    }
+}
+
 pub fn build_texture(device: &Device, size: PhysicalSize<u32>) -> Texture {
     device.create_texture(&TextureDescriptor {
         size: Extent3d {
@@ -85,7 +401,9 @@ pub fn build_texture(device: &Device, size: PhysicalSize<u32>) -> Texture {
             depth_or_array_layers: 1,
         },
         format: TextureFormat::Rgba8Unorm,
-        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::STORAGE_BINDING,
+        usage: TextureUsages::TEXTURE_BINDING
+            | TextureUsages::STORAGE_BINDING
+            | TextureUsages::COPY_SRC,
         label: None,
         mip_level_count: 1,
         sample_count: 1,
@@ -94,63 +412,193 @@ pub fn build_texture(device: &Device, size: PhysicalSize<u32>) -> Texture {
     })
 }
 
+pub fn build_object_id_texture(device: &Device, size: PhysicalSize<u32>) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        size: Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+        format: TextureFormat::R32Uint,
+        usage: TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC,
+        label: None,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        view_formats: &[TextureFormat::R32Uint],
+    })
+}
+
+/// Running average of the path traced samples, in full precision. Two of these are swapped every
+/// frame, one read and the other written.
+pub fn build_accumulation_texture(device: &Device, size: PhysicalSize<u32>) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        size: Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+        format: TextureFormat::Rgba32Float,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::STORAGE_BINDING,
+        label: None,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        view_formats: &[TextureFormat::Rgba32Float],
+    })
+}
+
+pub const COMPUTE_TEXTURE_LAYOUT_ENTRIES: &[BindGroupLayoutEntry] = &[
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::StorageTexture {
+            view_dimension: TextureViewDimension::D2,
+            access: StorageTextureAccess::WriteOnly,
+            format: TextureFormat::Rgba8Unorm,
+        },
+        count: None,
+    },
+    BindGroupLayoutEntry {
+        binding: 1,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::StorageTexture {
+            view_dimension: TextureViewDimension::D2,
+            access: StorageTextureAccess::WriteOnly,
+            format: TextureFormat::R32Uint,
+        },
+        count: None,
+    },
+    BindGroupLayoutEntry {
+        binding: 2,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    BindGroupLayoutEntry {
+        binding: 3,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    BindGroupLayoutEntry {
+        binding: 4,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    BindGroupLayoutEntry {
+        binding: 5,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::StorageTexture {
+            view_dimension: TextureViewDimension::D2,
+            access: StorageTextureAccess::WriteOnly,
+            format: TextureFormat::Rgba32Float,
+        },
+        count: None,
+    },
+];
+
+pub const RENDER_TEXTURE_LAYOUT_ENTRIES: &[BindGroupLayoutEntry] = &[
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    BindGroupLayoutEntry {
+        binding: 1,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Sampler(SamplerBindingType::NonFiltering),
+        count: None,
+    },
+];
+
 pub fn texture_bind_group_layouts(device: &Device) -> [BindGroupLayout; 2] {
     [
         device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: None,
-            entries: &[BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::COMPUTE,
-                ty: BindingType::StorageTexture {
-                    view_dimension: TextureViewDimension::D2,
-                    access: StorageTextureAccess::WriteOnly,
-                    format: TextureFormat::Rgba8Unorm,
-                },
-                count: None,
-            }],
+            entries: COMPUTE_TEXTURE_LAYOUT_ENTRIES,
         }),
         device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: None,
-            entries: &[
-                BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
-                },
-                BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: ShaderStages::FRAGMENT,
-                    ty: BindingType::Sampler(SamplerBindingType::NonFiltering),
-                    count: None,
-                },
-            ],
+            entries: RENDER_TEXTURE_LAYOUT_ENTRIES,
         }),
     ]
 }
 
+/// The compute bind groups for both directions of the accumulation ping-pong, the first reading
+/// `accumulation[0]` and writing `accumulation[1]`, and the render bind group.
+#[allow(clippy::too_many_arguments)]
 pub fn texture_bind_groups(
     device: &Device,
     texture: &Texture,
+    object_id_texture: &Texture,
+    blue_noise_texture: &Texture,
+    accumulation: &[Texture; 2],
+    tile_order: &Buffer,
     layouts: &[BindGroupLayout; 2],
     sampler: &Sampler,
     debug_texture: Option<&Texture>,
-) -> [BindGroup; 2] {
+) -> ([BindGroup; 2], [BindGroup; 2]) {
     let compute_view = texture.create_view(&TextureViewDescriptor::default());
+    let object_id_view = object_id_texture.create_view(&TextureViewDescriptor::default());
+    let blue_noise_view = blue_noise_texture.create_view(&TextureViewDescriptor::default());
+    let accumulation_views = accumulation
+        .each_ref()
+        .map(|t| t.create_view(&TextureViewDescriptor::default()));
     let render_view = debug_texture.map(|t| t.create_view(&TextureViewDescriptor::default()));
-    [
+    let compute_bind_group = |read: usize| {
         device.create_bind_group(&BindGroupDescriptor {
             label: None,
             layout: &layouts[0],
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: BindingResource::TextureView(&compute_view),
-            }],
-        }),
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&compute_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&object_id_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&blue_noise_view),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::Buffer(tile_order.as_entire_buffer_binding()),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(&accumulation_views[read]),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::TextureView(&accumulation_views[1 - read]),
+                },
+            ],
+        })
+    };
+    // The render pass shows the accumulation texture the compute pass just wrote, rather than the
+    // 8-bit output, so that the display transform gets radiance above 1.
+    let render_bind_group = |read: usize| {
         device.create_bind_group(&BindGroupDescriptor {
             label: None,
             layout: &layouts[1],
@@ -160,7 +608,7 @@ pub fn texture_bind_groups(
                     resource: BindingResource::TextureView(if let Some(ref view) = render_view {
                         view
                     } else {
-                        &compute_view
+                        &accumulation_views[1 - read]
                     }),
                 },
                 BindGroupEntry {
@@ -168,19 +616,43 @@ pub fn texture_bind_groups(
                     resource: BindingResource::Sampler(sampler),
                 },
             ],
-        }),
-    ]
+        })
+    };
+    (
+        [compute_bind_group(0), compute_bind_group(1)],
+        [render_bind_group(0), render_bind_group(1)],
+    )
 }
 
 pub fn build_compute_pipeline(
     device: &Device,
+    tile_size: TileSize,
     texture_bind_group_layout: &BindGroupLayout,
     camera_bind_group_layout: &BindGroupLayout,
+    settings_bind_group_layout: &BindGroupLayout,
+    scene_bind_group_layout: &BindGroupLayout,
 ) -> ComputePipeline {
-    let compute_shader = device.create_shader_module(include_wgsl!("compute.wgsl"));
+    let source = include_str!("compute.wgsl")
+        .replace(
+            "const TILE_WIDTH: u32 = 8u;",
+            &format!("const TILE_WIDTH: u32 = {}u;", tile_size.width),
+        )
+        .replace(
+            "const TILE_HEIGHT: u32 = 8u;",
+            &format!("const TILE_HEIGHT: u32 = {}u;", tile_size.height),
+        );
+    let compute_shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("compute.wgsl"),
+        source: ShaderSource::Wgsl(source.into()),
+    });
     let compute_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
         label: None,
-        bind_group_layouts: &[texture_bind_group_layout, camera_bind_group_layout],
+        bind_group_layouts: &[
+            texture_bind_group_layout,
+            camera_bind_group_layout,
+            settings_bind_group_layout,
+            scene_bind_group_layout,
+        ],
         push_constant_ranges: &[],
     });
     device.create_compute_pipeline(&ComputePipelineDescriptor {
@@ -195,12 +667,18 @@ pub fn build_compute_pipeline(
 pub fn build_render_pipeline(
     device: &Device,
     texture_bind_group_layout: &BindGroupLayout,
+    lut_bind_group_layout: &BindGroupLayout,
+    display_bind_group_layout: &BindGroupLayout,
     fragment_target_format: TextureFormat,
 ) -> RenderPipeline {
     let render_shader = device.create_shader_module(include_wgsl!("shader.wgsl"));
     let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
         label: None,
-        bind_group_layouts: &[texture_bind_group_layout],
+        bind_group_layouts: &[
+            texture_bind_group_layout,
+            lut_bind_group_layout,
+            display_bind_group_layout,
+        ],
         push_constant_ranges: &[],
     });
     device.create_render_pipeline(&RenderPipelineDescriptor {
@@ -229,14 +707,86 @@ pub fn build_render_pipeline(
     })
 }
 
-pub fn build_debug_texture(device: &Device, queue: &Queue) -> Texture {
-    let debug_img = image::io::Reader::open("debug.png")
-        .unwrap()
-        .decode()
-        .unwrap()
-        .into_rgba8();
-    let size = (debug_img.width(), debug_img.height());
-    let data = debug_img.as_raw();
+/// How the texels of an image file are interpreted once uploaded.
+///
+/// Colour textures (albedo, emission, photographs, the debug image) are authored in sRGB and
+/// must be uploaded as `Rgba8UnormSrgb` so the sampler converts them to linear for us. Data
+/// textures (normal maps, roughness or mask maps) already hold linear values and stay
+/// `Rgba8Unorm`, otherwise the decode would bend them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureKind {
+    Color,
+    Data,
+}
+
+impl TextureKind {
+    pub fn format(&self) -> TextureFormat {
+        match self {
+            TextureKind::Color => TextureFormat::Rgba8UnormSrgb,
+            TextureKind::Data => TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub fn load_texture(
+    device: &Device,
+    queue: &Queue,
+    path: impl AsRef<Path>,
+    kind: TextureKind,
+) -> ImageResult<Texture> {
+    Ok(upload_image(device, queue, &load_image(path)?, kind))
+}
+
+pub fn load_image(path: impl AsRef<Path>) -> ImageResult<RgbaImage> {
+    Ok(image::io::Reader::open(path)?.decode()?.into_rgba8())
+}
+
+/// Like `load_texture`, but a missing or unreadable image is replaced by `placeholder_image`
+/// with a warning, so the rest of the scene still renders.
+#[cfg_attr(not(feature = "debug-texture"), allow(dead_code))]
+pub fn load_texture_or_placeholder(
+    device: &Device,
+    queue: &Queue,
+    path: impl AsRef<Path>,
+    kind: TextureKind,
+) -> Texture {
+    upload_image(device, queue, &load_image_or_placeholder(path), kind)
+}
+
+pub fn load_image_or_placeholder(path: impl AsRef<Path>) -> RgbaImage {
+    let path = path.as_ref();
+    load_image(path).unwrap_or_else(|e| {
+        eprintln!(
+            "Failed to load texture {}: {}, using a placeholder",
+            path.display(),
+            e
+        );
+        placeholder_image()
+    })
+}
+
+/// Magenta and black checkerboard standing in for textures that failed to load.
+pub fn placeholder_image() -> RgbaImage {
+    const SIZE: u32 = 64;
+    const CHECK: u32 = 8;
+    RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        if ((x / CHECK) ^ (y / CHECK)) & 1 == 0 {
+            image::Rgba([255, 0, 255, 255])
+        } else {
+            image::Rgba([0, 0, 0, 255])
+        }
+    })
+}
+
+#[allow(dead_code)]
+pub fn placeholder_texture(device: &Device, queue: &Queue) -> Texture {
+    upload_image(device, queue, &placeholder_image(), TextureKind::Color)
+}
+
+pub fn upload_image(device: &Device, queue: &Queue, img: &RgbaImage, kind: TextureKind) -> Texture {
+    let size = (img.width(), img.height());
+    let format = kind.format();
 
     device.create_texture_with_data(
         queue,
@@ -246,15 +796,295 @@ pub fn build_debug_texture(device: &Device, queue: &Queue) -> Texture {
                 height: size.1,
                 depth_or_array_layers: 1,
             },
-            format: TextureFormat::Rgba8Unorm,
+            format,
+            usage: TextureUsages::TEXTURE_BINDING,
+            label: None,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            view_formats: &[format],
+        },
+        TextureDataOrder::LayerMajor,
+        img.as_raw(),
+    )
+}
+
+/// Decodes a high dynamic range image such as a Radiance `.hdr` or OpenEXR `.exr` file, keeping
+/// its linear values above 1. Other formats load too, as their 0..1 range.
+pub fn load_hdr_image(path: impl AsRef<Path>) -> ImageResult<Rgba32FImage> {
+    Ok(image::io::Reader::open(path)?.decode()?.into_rgba32f())
+}
+
+/// Uploads as `Rgba16Float`, which unlike `Rgba32Float` can be sampled with filtering on every
+/// adapter.
+pub fn upload_hdr_image(device: &Device, queue: &Queue, img: &Rgba32FImage) -> Texture {
+    let texels: Vec<f16> = img.as_raw().iter().copied().map(f16::from_f32).collect();
+    let format = TextureFormat::Rgba16Float;
+
+    device.create_texture_with_data(
+        queue,
+        &TextureDescriptor {
+            size: Extent3d {
+                width: img.width(),
+                height: img.height(),
+                depth_or_array_layers: 1,
+            },
+            format,
             usage: TextureUsages::TEXTURE_BINDING,
             label: None,
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            view_formats: &[TextureFormat::Rgba8Unorm],
+            view_formats: &[format],
         },
         TextureDataOrder::LayerMajor,
-        data,
+        bytemuck::cast_slice(&texels),
     )
 }
+
+/// A 64x64 tileable blue-noise mask (void-and-cluster, uniformly distributed ranks) used to
+/// dither the 8-bit output.
+pub fn build_blue_noise_texture(device: &Device, queue: &Queue) -> Texture {
+    let img = image::load_from_memory(include_bytes!("../assets/blue_noise.png"))
+        .unwrap()
+        .into_rgba8();
+    upload_image(device, queue, &img, TextureKind::Data)
+}
+
+/// Decodes the icon embedded in the binary. Platforms without per-window icons (Wayland, macOS)
+/// simply ignore it.
+pub fn window_icon() -> Option<Icon> {
+    let icon = image::load_from_memory(include_bytes!("../assets/icon.png"))
+        .map_err(|e| e.to_string())
+        .and_then(|img| {
+            let img = img.into_rgba8();
+            let (width, height) = img.dimensions();
+            Icon::from_rgba(img.into_raw(), width, height).map_err(|e| e.to_string())
+        });
+    match icon {
+        Ok(icon) => Some(icon),
+        Err(e) => {
+            eprintln!("Failed to load the window icon: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(feature = "debug-texture")]
+pub fn build_debug_texture(device: &Device, queue: &Queue) -> Texture {
+    load_texture_or_placeholder(device, queue, "debug.png", TextureKind::Color)
+}
+
+/// A device on any adapter, software ones included, for tests that need GPU resources.
+#[cfg(test)]
+pub fn test_device() -> (Device, Queue) {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter = pollster::block_on(instance.request_adapter(&Default::default()))
+        .expect("no adapter to run GPU tests on");
+    pollster::block_on(adapter.request_device(&Default::default(), None)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-6, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn rotate_i_around_k() {
+        assert_close(Vec3::i().rotate_around(&Vec3::k(), FRAC_PI_2), Vec3::j());
+    }
+
+    #[test]
+    fn rotate_around_unnormalised_axis() {
+        assert_close(
+            Vec3::i().rotate_around(&(Vec3::k() * 3.0), FRAC_PI_2),
+            Vec3::j(),
+        );
+    }
+
+    #[test]
+    fn angle_between_basis_vectors() {
+        for (a, b) in [
+            (Vec3::i(), Vec3::j()),
+            (Vec3::j(), Vec3::k()),
+            (Vec3::k(), Vec3::i()),
+        ] {
+            assert!((a.angle_between(&b) - FRAC_PI_2).abs() < 1e-6);
+        }
+        assert_eq!(Vec3::i().angle_between(&(Vec3::i() * 2.0)), 0.0);
+    }
+
+    #[test]
+    fn swizzle_reorders_components() {
+        let v = Vec3(1.0, 2.0, 3.0);
+        assert_eq!(v.yzx(), Vec3(2.0, 3.0, 1.0));
+        assert_eq!(v.zxy(), Vec3(3.0, 1.0, 2.0));
+        assert_eq!(v.swizzle(0, 0, 2), Vec3(1.0, 1.0, 3.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn swizzle_out_of_bounds() {
+        Vec3(1.0, 2.0, 3.0).swizzle(0, 1, 3);
+    }
+
+    #[test]
+    fn missing_image_yields_placeholder() {
+        let img = load_image_or_placeholder("does/not/exist.png");
+        assert_eq!(img, placeholder_image());
+        assert_eq!(img.get_pixel(0, 0), &image::Rgba([255, 0, 255, 255]));
+        assert_eq!(img.get_pixel(8, 0), &image::Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn corrupt_image_yields_placeholder() {
+        let path = std::env::temp_dir().join("ray-tracing-webgpu-corrupt.png");
+        std::fs::write(&path, b"not a png").unwrap();
+        let img = load_image_or_placeholder(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(img, placeholder_image());
+    }
+
+    #[test]
+    fn dot_of_orthogonal_vectors_is_zero() {
+        assert_eq!(Vec3::i().dot(Vec3::j()), 0.0);
+        assert_eq!(Vec3(1.0, 2.0, 0.0).dot(Vec3(-2.0, 1.0, 5.0)), 0.0);
+        assert_eq!(Vec3(1.0, 2.0, 3.0).dot(Vec3(4.0, 5.0, 6.0)), 32.0);
+    }
+
+    #[test]
+    fn cross_follows_right_hand_rule() {
+        assert_eq!(Vec3::i().cross(Vec3::j()), Vec3::k());
+        assert_eq!(Vec3::j().cross(Vec3::k()), Vec3::i());
+        assert_eq!(Vec3::j().cross(Vec3::i()), -Vec3::k());
+    }
+
+    #[test]
+    fn length() {
+        assert_eq!(Vec3(3.0, 4.0, 0.0).length_squared(), 25.0);
+        assert_eq!(Vec3(3.0, 4.0, 0.0).length(), 5.0);
+    }
+
+    #[test]
+    fn normalize_gives_unit_length() {
+        for v in [
+            Vec3(3.0, 4.0, 0.0),
+            Vec3(-1.0, 2.0, 7.5),
+            Vec3(1e-3, 0.0, 1e-3),
+        ] {
+            assert!((v.normalize().length() - 1.0).abs() <= f32::EPSILON * 4.0);
+        }
+    }
+
+    #[test]
+    fn normalize_zero_is_zero() {
+        assert_eq!(Vec3::origin().normalize(), Vec3::origin());
+    }
+
+    #[test]
+    fn vec3_uniform_is_16_bytes() {
+        assert_eq!(std::mem::size_of::<Vec3Uniform>(), 16);
+        assert_eq!(std::mem::align_of::<Vec3Uniform>(), 16);
+    }
+
+    #[test]
+    fn to_uniform_keeps_components() {
+        let uniform = Vec3(1.0, 2.0, 3.0).to_uniform();
+        assert_eq!(
+            bytemuck::cast_slice::<_, f32>(&[uniform]),
+            [1.0, 2.0, 3.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn reflect_off_normal() {
+        assert_eq!(Vec3(1.0, -1.0, 0.0).reflect(Vec3::j()), Vec3(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn refract_head_on_goes_straight_through() {
+        assert_close((-Vec3::j()).refract(Vec3::j(), 1.0 / 1.5), -Vec3::j());
+    }
+
+    #[test]
+    fn refract_obeys_snell() {
+        let ratio = 1.0 / 1.5;
+        let incoming = Vec3(1.0, -1.0, 0.0).normalize();
+        let refracted = incoming.refract(Vec3::j(), ratio);
+        let sin_in = incoming.cross(Vec3::j()).length();
+        let sin_out = refracted.cross(Vec3::j()).length();
+        assert!((sin_out - ratio * sin_in).abs() < 1e-6);
+        assert!((refracted.length() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn total_internal_reflection_is_finite() {
+        // Leaving glass at a grazing angle: sin θ' = 1.5 sin θ > 1.
+        let incoming = Vec3(1.0, -0.1, 0.0).normalize();
+        let refracted = incoming.refract(Vec3::j(), 1.5);
+        assert!(refracted.as_array().iter().all(|c| c.is_finite()));
+    }
+
+    #[test]
+    fn mul_is_component_wise() {
+        let product = Vec3(1.0, 2.0, 3.0) * Vec3(4.0, 5.0, 6.0);
+        assert_eq!(product, Vec3(4.0, 10.0, 18.0));
+        assert_eq!(
+            Vec3(1.0, 2.0, 3.0).component_mul(Vec3(4.0, 5.0, 6.0)),
+            product
+        );
+    }
+
+    #[test]
+    fn mul_by_scalar_still_scales() {
+        assert_eq!(Vec3(1.0, 2.0, 3.0) * 2.0, Vec3(2.0, 4.0, 6.0));
+    }
+
+    #[test]
+    fn index_reads_components() {
+        let v = Vec3(1.0, 2.0, 3.0);
+        assert_eq!([v[0], v[1], v[2]], v.as_array());
+    }
+
+    #[test]
+    fn index_mut_writes_components() {
+        let mut v = Vec3::origin();
+        v[0] = 1.0;
+        v[1] = 2.0;
+        v[2] = 3.0;
+        assert_eq!(v, Vec3(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn index_out_of_bounds() {
+        let _ = Vec3::origin()[3];
+    }
+
+    #[test]
+    fn assign_ops_match_binary_ops() {
+        let a = Vec3(1.0, 2.0, 3.0);
+        let b = Vec3(4.0, -5.0, 0.5);
+
+        let mut v = a;
+        v += b;
+        assert_eq!(v, a + b);
+
+        let mut v = a;
+        v -= b;
+        assert_eq!(v, a - b);
+
+        let mut v = a;
+        v *= 3.0;
+        assert_eq!(v, a * 3.0);
+
+        let mut v = a;
+        v /= 4.0;
+        assert_eq!(v, a / 4.0);
+    }
+}